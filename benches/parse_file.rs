@@ -0,0 +1,49 @@
+// Compares `parse_file` and `parse_file_parallel` over a large file built from the R119 sample
+//
+// cargo bench --features rayon
+
+use iso8583::file_utils;
+use std::time::{Duration, Instant};
+
+const COPIES: usize = 2_000;
+const ITERATIONS: u32 = 5;
+
+fn large_file() -> Vec<u8> {
+    let sample = file_utils::read_file("tests/R119_files_processor.ipm").unwrap();
+    let (payload, records) = file_utils::deblock_into_records(sample).unwrap();
+    let records: Vec<Vec<u8>> = records
+        .expect("R119 sample should have RDWs")
+        .into_iter()
+        .map(|record| payload[record].to_vec())
+        .collect();
+
+    let copies: Vec<Vec<u8>> = (0..COPIES).flat_map(|_| records.iter().cloned()).collect();
+
+    file_utils::block(file_utils::add_rdw_to(&copies))
+}
+
+fn bench(name: &str, payload: &[u8], parse: fn(Vec<u8>) -> eyre::Result<iso8583::Iso8583File>) -> Duration {
+    let mut total = Duration::ZERO;
+    let mut messages = 0;
+
+    for _ in 0..ITERATIONS {
+        let payload = payload.to_vec();
+        let start = Instant::now();
+        messages = parse(payload).unwrap().messages.len();
+        total += start.elapsed();
+    }
+
+    let average = total / ITERATIONS;
+    println!("{:<20} {:>8} messages {:>10.2?}/iter", name, messages, average);
+    average
+}
+
+fn main() {
+    let payload = large_file();
+    println!("{} bytes", payload.len());
+
+    let sequential = bench("parse_file", &payload, iso8583::parse_file);
+    let parallel = bench("parse_file_parallel", &payload, iso8583::parse_file_parallel);
+
+    println!("speedup {:.2}x", sequential.as_secs_f64() / parallel.as_secs_f64());
+}
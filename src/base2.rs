@@ -0,0 +1,404 @@
+use crate::decoders::{Arn, Pan};
+use eyre::{eyre, Result};
+use std::collections::HashMap;
+use std::fmt;
+use strum::{EnumProperty, IntoEnumIterator};
+use strum_macros;
+
+pub const RECORD_SIZE: usize = 168;
+
+/// A field of a TC record, positioned as on the Visa specs (1-based)
+struct RecordField {
+    name: &'static str,
+    position: usize,
+    length: usize,
+}
+
+const fn field(name: &'static str, position: usize, length: usize) -> RecordField {
+    RecordField { name, position, length }
+}
+
+const DRAFT_TCR0: &[RecordField] = &[
+    field("account_number", 5, 16),
+    field("account_number_extension", 21, 3),
+    field("floor_limit_indicator", 24, 1),
+    field("crb_exception_file_indicator", 25, 1),
+    field("pcas_indicator", 26, 1),
+    field("acquirer_reference_number", 27, 23),
+    field("acquirer_business_id", 50, 8),
+    field("purchase_date", 58, 4),
+    field("destination_amount", 62, 12),
+    field("destination_currency_code", 74, 3),
+    field("source_amount", 77, 12),
+    field("source_currency_code", 89, 3),
+    field("merchant_name", 92, 25),
+    field("merchant_city", 117, 13),
+    field("merchant_country_code", 130, 3),
+    field("merchant_category_code", 133, 4),
+    field("merchant_zip_code", 137, 5),
+    field("merchant_state_province_code", 142, 3),
+    field("requested_payment_service", 145, 1),
+    field("number_of_payment_forms", 146, 1),
+    field("usage_code", 147, 1),
+    field("reason_code", 148, 2),
+    field("settlement_flag", 150, 1),
+    field("authorization_characteristics_indicator", 151, 1),
+    field("authorization_code", 152, 6),
+    field("pos_terminal_capability", 158, 1),
+    field("international_fee_indicator", 159, 1),
+    field("cardholder_id_method", 160, 1),
+    field("collection_only_flag", 161, 1),
+    field("pos_entry_mode", 162, 2),
+    field("central_processing_date", 164, 4),
+    field("reimbursement_attribute", 168, 1),
+];
+
+const DRAFT_TCR1: &[RecordField] = &[
+    field("issuer_workstation_bin", 5, 6),
+    field("acquirer_workstation_bin", 11, 6),
+    field("chargeback_reference_number", 17, 6),
+    field("documentation_indicator", 23, 1),
+    field("member_message_text", 24, 50),
+    field("special_condition_indicators", 74, 2),
+    field("fee_program_indicator", 76, 3),
+    field("issuer_charge", 79, 1),
+    field("card_acceptor_id", 81, 15),
+    field("terminal_id", 96, 8),
+    field("national_reimbursement_fee", 104, 12),
+    field("mail_telephone_or_electronic_commerce_indicator", 116, 1),
+    field("special_chargeback_indicator", 117, 1),
+    field("interface_trace_number", 118, 6),
+    field("unattended_acceptance_terminal_indicator", 124, 1),
+    field("prepaid_card_indicator", 125, 1),
+    field("service_development_field", 126, 1),
+    field("avs_response_code", 127, 1),
+    field("authorization_source_code", 128, 1),
+    field("purchase_identifier_format", 129, 1),
+    field("account_selection", 130, 1),
+    field("installment_payment_count", 131, 2),
+    field("purchase_identifier", 133, 25),
+    field("cashback", 158, 9),
+    field("chip_condition_code", 167, 1),
+    field("pos_environment", 168, 1),
+];
+
+/// Each category is identified by its transaction code (TC)
+///
+/// `layout = "draft"` marks the records whose TCR0 and TCR1 fields are decoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, strum_macros::EnumProperty, strum_macros::EnumIter)]
+pub enum Base2Category {
+    // Draft messages
+    #[strum(props(tc = "05", name = "sales_drafts", kind = "draft_messages", layout = "draft"))]
+    SalesDraft,
+    #[strum(props(tc = "06", name = "credit_vouchers", kind = "draft_messages", layout = "draft"))]
+    CreditVoucher,
+    #[strum(props(tc = "07", name = "cash_disbursements", kind = "draft_messages", layout = "draft"))]
+    CashDisbursement,
+
+    // Chargebacks
+    #[strum(props(tc = "15", name = "sales_draft_chargebacks", kind = "chargeback_messages", layout = "draft"))]
+    SalesDraftChargeback,
+    #[strum(props(tc = "16", name = "credit_voucher_chargebacks", kind = "chargeback_messages", layout = "draft"))]
+    CreditVoucherChargeback,
+    #[strum(props(tc = "17", name = "cash_disbursement_chargebacks", kind = "chargeback_messages", layout = "draft"))]
+    CashDisbursementChargeback,
+
+    // Reversals
+    #[strum(props(tc = "25", name = "sales_draft_reversals", kind = "reversal_messages", layout = "draft"))]
+    SalesDraftReversal,
+    #[strum(props(tc = "26", name = "credit_voucher_reversals", kind = "reversal_messages", layout = "draft"))]
+    CreditVoucherReversal,
+    #[strum(props(tc = "27", name = "cash_disbursement_reversals", kind = "reversal_messages", layout = "draft"))]
+    CashDisbursementReversal,
+
+    // Other messages, kept raw
+    #[strum(props(tc = "33", name = "general_deliveries", kind = "general_delivery_messages"))]
+    GeneralDelivery,
+    #[strum(props(tc = "46", name = "member_settlement_data", kind = "settlement_messages"))]
+    MemberSettlementData,
+
+    // File layout messages
+    #[strum(props(tc = "90", name = "headers", kind = "file_layout_messages"))]
+    Header,
+    #[strum(props(tc = "91", name = "batch_trailers", kind = "file_layout_messages"))]
+    BatchTrailer,
+    #[strum(props(tc = "92", name = "file_trailers", kind = "file_layout_messages"))]
+    FileTrailer,
+
+    // not intended to be used
+    #[strum(props(tc = "unknown", name = "unknown", kind = "unknown"))]
+    Unknown,
+}
+
+impl Base2Category {
+    fn from_transaction_code(transaction_code: &str) -> Base2Category {
+        Base2Category::iter()
+            .find(|category| category.get_str("tc") == Some(transaction_code))
+            .unwrap_or(Base2Category::Unknown)
+    }
+
+    fn has_draft_layout(&self) -> bool {
+        self.get_str("layout") == Some("draft")
+    }
+}
+
+/// A transaction, made of its TCR0 and the following component records
+#[derive(Debug, Clone, Serialize)]
+pub struct Base2Message {
+    pub transaction_code: String,
+    pub category: Base2Category,
+    /// Decoded fields, with trailing spaces removed
+    pub fields: HashMap<String, String>,
+    /// Raw TC records, where `records[n]` is the TCRn
+    pub records: Vec<Vec<u8>>,
+}
+
+impl Base2Message {
+    fn new(tcr0: &[u8]) -> Result<Base2Message> {
+        let transaction_code = record_str(tcr0, 1, 2)?;
+        let category = Base2Category::from_transaction_code(&transaction_code);
+
+        let mut message = Base2Message {
+            transaction_code,
+            category,
+            fields: HashMap::new(),
+            records: vec![],
+        };
+        message.fields.insert("transaction_code".to_string(), message.transaction_code.clone());
+        message
+            .fields
+            .insert("transaction_code_qualifier".to_string(), record_str(tcr0, 3, 1)?);
+        message.push_record(tcr0)?;
+
+        Ok(message)
+    }
+
+    fn push_record(&mut self, record: &[u8]) -> Result<()> {
+        let layout = match (self.category.has_draft_layout(), self.records.len()) {
+            (true, 0) => DRAFT_TCR0,
+            (true, 1) => DRAFT_TCR1,
+            _ => &[],
+        };
+
+        for record_field in layout {
+            let value = record_str(record, record_field.position, record_field.length)?;
+            self.fields.insert(record_field.name.to_string(), value.trim_end().to_string());
+        }
+
+        self.records.push(record.to_vec());
+        Ok(())
+    }
+
+    /// Decodes the account number (with its extension), returns `None` on records without one
+    pub fn pan(&self) -> Result<Option<Pan>> {
+        match self.fields.get("account_number") {
+            Some(account_number) => {
+                let extension = self.fields.get("account_number_extension").map(String::as_str).unwrap_or_default();
+                Ok(Some(Pan::decode(&format!("{}{}", account_number, extension))?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn arn(&self) -> Result<Option<Arn>> {
+        self.fields
+            .get("acquirer_reference_number")
+            .map(|value| Arn::decode(value))
+            .transpose()
+    }
+}
+
+fn record_str(record: &[u8], position: usize, length: usize) -> Result<String> {
+    let bytes = record
+        .get(position - 1..position - 1 + length)
+        .ok_or_else(|| eyre!("record too short for position {} and length {}", position, length))?;
+
+    Ok(String::from_utf8_lossy(bytes).to_string())
+}
+
+/// A Visa BASE II clearing file, made of fixed length (168 bytes) TC records
+///
+/// Each transaction starts on its TCR0 (transaction component record) and may be followed by
+/// TCR1, TCR2... sharing the same transaction code. Draft records (TC05, TC06, TC07, their
+/// chargebacks and reversals) have their TCR0 and TCR1 fields decoded, other records are kept raw.
+#[derive(Clone, Serialize)]
+pub struct Base2File {
+    pub messages: Vec<Base2Message>,
+    pub categories_indexes: HashMap<String, Vec<usize>>,
+}
+
+impl fmt::Debug for Base2File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formatted_messages: String = self
+            .messages
+            .iter()
+            .map(|message| format!("\n {:?} => {:?}", message.category, message.fields))
+            .collect();
+
+        write!(f, "{}", formatted_messages)
+    }
+}
+
+impl Base2File {
+    fn new(messages: Vec<Base2Message>) -> Self {
+        let mut categories_indexes: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, message) in messages.iter().enumerate() {
+            let category_name = message.category.get_str("name").unwrap_or_default().to_string();
+            categories_indexes.entry(category_name).or_default().push(index);
+        }
+
+        Base2File {
+            messages,
+            categories_indexes,
+        }
+    }
+
+    pub fn messages_count(self) -> HashMap<String, usize> {
+        self.categories_indexes
+            .into_iter()
+            .map(|(category_name, indexes)| (category_name, indexes.len()))
+            .collect()
+    }
+
+    /// Keeps the messages having any of the searched values on any of the searched fields,
+    /// e.g `{"merchant_category_code": ["5411"]}`
+    pub fn search(self, search: HashMap<String, Vec<String>>) -> Base2File {
+        let messages = self
+            .messages
+            .into_iter()
+            .filter(|message| {
+                search.iter().any(|(field_name, values)| {
+                    message.fields.get(field_name).map_or(false, |value| values.contains(value))
+                })
+            })
+            .collect();
+
+        Base2File::new(messages)
+    }
+}
+
+/// Splits the payload into 168 bytes records, accepting records terminated by a new line
+/// (with trailing spaces possibly removed) or simply concatenated
+fn split_records(payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+    if !payload.contains(&b'\n') {
+        if payload.len() % RECORD_SIZE != 0 {
+            return Err(eyre!("file size {} isn't a multiple of {}", payload.len(), RECORD_SIZE));
+        }
+        return Ok(payload.chunks(RECORD_SIZE).map(|record| record.to_vec()).collect());
+    }
+
+    payload
+        .split(|&c| c == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            if line.len() > RECORD_SIZE {
+                return Err(eyre!("record with {} bytes, max is {}", line.len(), RECORD_SIZE));
+            }
+            let mut record = line.to_vec();
+            record.resize(RECORD_SIZE, b' ');
+            Ok(record)
+        })
+        .collect()
+}
+
+/// Parses a BASE II file, chaining each TCR0 with its following component records
+pub fn parse_file(payload: Vec<u8>) -> Result<Base2File> {
+    let mut messages: Vec<Base2Message> = vec![];
+
+    for (record_index, record) in split_records(&payload)?.iter().enumerate() {
+        let transaction_code = record_str(record, 1, 2)?;
+        let sequence_number = record_str(record, 4, 1)?
+            .parse::<usize>()
+            .map_err(|_| eyre!("invalid tcr number on record {}", record_index))?;
+
+        if sequence_number == 0 {
+            messages.push(Base2Message::new(record)?);
+            continue;
+        }
+
+        match messages.last_mut() {
+            Some(message)
+                if message.transaction_code == transaction_code && message.records.len() == sequence_number =>
+            {
+                message.push_record(record)?
+            }
+            _ => {
+                return Err(eyre!(
+                    "record {} (tc {} tcr{}) doesn't follow its previous component record",
+                    record_index,
+                    transaction_code,
+                    sequence_number
+                ))
+            }
+        }
+    }
+
+    Ok(Base2File::new(messages))
+}
+
+#[cfg(test)]
+fn test_record(values: &[(usize, &str)]) -> Vec<u8> {
+    let mut record = vec![b' '; RECORD_SIZE];
+    for (position, value) in values {
+        record[position - 1..position - 1 + value.len()].copy_from_slice(value.as_bytes());
+    }
+    record
+}
+
+#[test]
+fn test_parse_draft_records() {
+    let mut payload = test_record(&[(1, "9000"), (5, "HEADER")]);
+    payload.append(&mut test_record(&[
+        (1, "0500"),
+        (5, "5499999999999998"),
+        (27, "75123453123000000000015"),
+        (62, "000000001500"),
+        (92, "MERCHANT"),
+        (133, "5411"),
+    ]));
+    payload.append(&mut test_record(&[(1, "0501"), (81, "CARDACCEPTOR01"), (96, "TERM0001")]));
+    payload.append(&mut test_record(&[(1, "0600"), (5, "4111111111111111"), (133, "5812")]));
+    payload.append(&mut test_record(&[(1, "3300"), (5, "GENERAL DELIVERY")]));
+
+    let base2_file = parse_file(payload).unwrap();
+    assert_eq!(base2_file.messages.len(), 4);
+
+    let sales_draft = &base2_file.messages[1];
+    assert_eq!(sales_draft.category, Base2Category::SalesDraft);
+    assert_eq!(sales_draft.records.len(), 2);
+    assert_eq!(sales_draft.fields["merchant_name"], "MERCHANT");
+    assert_eq!(sales_draft.fields["terminal_id"], "TERM0001");
+    assert_eq!(sales_draft.pan().unwrap().unwrap().value, "5499999999999998");
+    assert!(sales_draft.arn().unwrap().unwrap().has_valid_check_digit());
+
+    let general_delivery = &base2_file.messages[3];
+    assert_eq!(general_delivery.category, Base2Category::GeneralDelivery);
+    assert!(!general_delivery.fields.contains_key("account_number"));
+
+    let search = HashMap::from([("merchant_category_code".to_string(), vec!["5812".to_string()])]);
+    let credit_vouchers = base2_file.clone().search(search);
+    assert_eq!(credit_vouchers.messages.len(), 1);
+    assert_eq!(credit_vouchers.categories_indexes["credit_vouchers"], vec![0]);
+
+    let messages_count = base2_file.messages_count();
+    assert_eq!(messages_count["sales_drafts"], 1);
+    assert_eq!(messages_count["headers"], 1);
+}
+
+#[test]
+fn test_records_chaining_and_line_terminators() {
+    let tcr0 = test_record(&[(1, "0500")]);
+    let tcr1 = test_record(&[(1, "0501")]);
+    let tcr2_of_other_tc = test_record(&[(1, "0602")]);
+
+    let mut lines = tcr0.clone();
+    lines.extend_from_slice(b"\r\n0501");
+    lines.push(b'\n');
+    let base2_file = parse_file(lines).unwrap();
+    assert_eq!(base2_file.messages[0].records, vec![tcr0.clone(), tcr1.clone()]);
+
+    assert!(parse_file([tcr1.clone(), tcr0.clone()].concat()).is_err());
+    assert!(parse_file([tcr0.clone(), tcr1, tcr2_of_other_tc].concat()).is_err());
+    assert!(parse_file(tcr0[0..100].to_vec()).is_err());
+}
@@ -0,0 +1,72 @@
+use iso8583::diff::{diff_files, Alignment};
+use iso8583::query::Selector;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: ipm-diff [--json] [--key 031,071,0158] <left file> <right file>
+
+Compares two IPM files message by message, by position or by the values of the given
+DEs (3 digits label ids) and PDS (4 digits ids). Exits with 1 when the files differ";
+
+fn parse_selector(key: &str) -> Result<Selector, String> {
+    match key.len() {
+        3 => key
+            .parse::<usize>()
+            .map(Selector::De)
+            .map_err(|_| format!("invalid DE {:?}", key)),
+        4 if key.chars().all(|c| c.is_ascii_digit()) => Ok(Selector::Pds(key.to_string())),
+        _ => Err(format!("invalid key {:?}, expecting a DE (e.g 031) or a PDS (e.g 0158)", key)),
+    }
+}
+
+fn run(args: Vec<String>) -> Result<bool, String> {
+    let mut json = false;
+    let mut alignment = Alignment::Position;
+    let mut files = vec![];
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--key" => {
+                let keys = args.next().ok_or("--key expects a list of DEs and PDS")?;
+                let selectors = keys.split(',').map(parse_selector).collect::<Result<Vec<Selector>, String>>()?;
+                alignment = Alignment::Key(selectors);
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => files.push(arg),
+        }
+    }
+
+    if files.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let parse = |file_name: &str| {
+        iso8583::file_utils::read_file(file_name)
+            .and_then(iso8583::parse_file)
+            .map_err(|e| format!("unable to parse {}: {}", file_name, e))
+    };
+    let left = parse(&files[0])?;
+    let right = parse(&files[1])?;
+
+    let file_diff = diff_files(&left, &right, &alignment);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&file_diff).map_err(|e| e.to_string())?);
+    } else {
+        print!("{}", file_diff);
+    }
+
+    Ok(file_diff.is_empty())
+}
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        }
+    }
+}
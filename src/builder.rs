@@ -0,0 +1,337 @@
+use crate::data_elements::DataElements;
+use crate::iso_field::{FieldSizeType, IPMValue};
+use crate::iso_specs::IsoSpecs;
+use crate::pds::{PdsCollection, PDS_MAX_LEN};
+use crate::writer;
+use crate::Message;
+use eyre::{eyre, Result};
+use std::collections::BTreeMap;
+use strum::EnumProperty;
+
+/// Builds a `Message` field by field, validating each value against the `IsoSpecs`
+///
+/// Errors are accumulated and returned by `build`, so calls can be chained:
+///
+/// ```
+/// use iso8583::builder::MessageBuilder;
+/// use iso8583::iso_specs::IsoSpecs;
+///
+/// let specs = IsoSpecs::new();
+/// let header = MessageBuilder::new(&specs)
+///     .mti("1644")
+///     .de(24, "697")
+///     .de(71, "1")
+///     .pds("0105", "0012303040000002337904401")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(header.data_elements["048"].get_string(), "01050250012303040000002337904401");
+/// ```
+pub struct MessageBuilder<'a> {
+    specs: &'a IsoSpecs,
+    mti: Option<String>,
+    data_elements: BTreeMap<usize, IPMValue>,
+    pds: BTreeMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl<'a> MessageBuilder<'a> {
+    pub fn new(specs: &'a IsoSpecs) -> MessageBuilder<'a> {
+        MessageBuilder {
+            specs,
+            mti: None,
+            data_elements: BTreeMap::new(),
+            pds: BTreeMap::new(),
+            errors: vec![],
+        }
+    }
+
+    pub fn mti(mut self, mti: &str) -> Self {
+        if mti.len() == 4 && mti.chars().all(|c| c.is_ascii_digit()) {
+            self.mti = Some(mti.to_string());
+        } else {
+            self.errors.push(format!("invalid mti {:?}", mti));
+        }
+        self
+    }
+
+    /// Sets a DE from its textual representation, e.g `.de(24, "200")`
+    pub fn de(mut self, field_number: usize, value: &str) -> Self {
+        match self.ipm_value(field_number, value) {
+            Ok(ipm_value) => {
+                self.data_elements.insert(field_number, ipm_value);
+            }
+            Err(e) => self.errors.push(e.to_string()),
+        }
+        self
+    }
+
+    /// Sets a binary DE, e.g `.de_binary(55, &icc_data)`
+    pub fn de_binary(mut self, field_number: usize, value: &[u8]) -> Self {
+        match self.field_spec_label_id(field_number) {
+            Ok(label_id) => {
+                let iso_field = self.specs.field(&label_id).expect("spec checked on label id");
+
+                if value.len() > iso_field.length {
+                    self.errors.push(format!(
+                        "{} has {} bytes, max is {}",
+                        iso_field.label,
+                        value.len(),
+                        iso_field.length
+                    ));
+                } else if iso_field.char_type.get_str("content_type") != Some("binary") {
+                    self.errors.push(format!("{} is not a binary data element", iso_field.label));
+                } else {
+                    self.data_elements.insert(field_number, IPMValue::Binary(value.to_vec()));
+                }
+            }
+            Err(e) => self.errors.push(e.to_string()),
+        }
+        self
+    }
+
+    /// Adds a PDS, which will be encoded into DE 48 ordered by id, overflowing into the next PDS data
+    /// elements (DE 62, 123, 124 and 125 on IPM messages)
+    pub fn pds(mut self, pds_id: &str, value: &str) -> Self {
+        if pds_id.len() != 4 || !pds_id.chars().all(|c| c.is_ascii_digit()) {
+            self.errors.push(format!("invalid pds id {:?}", pds_id));
+        } else if value.len() > PDS_MAX_LEN {
+            self.errors.push(format!("pds {} has {} characters, max is {}", pds_id, value.len(), PDS_MAX_LEN));
+        } else {
+            self.pds.insert(pds_id.to_string(), value.to_string());
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Message> {
+        let mut errors = self.errors;
+
+        let mti = match self.mti {
+            Some(mti) => mti,
+            None => {
+                errors.push("mti is mandatory".to_string());
+                String::new()
+            }
+        };
+
+        let mut data_elements: DataElements = self.data_elements.into_iter().collect();
+
+        let mut pds: PdsCollection = self.pds.iter().map(|(pds_id, value)| (pds_id.as_str(), value.as_str())).collect();
+
+        if !pds.is_empty() {
+            match pds.distribute(&self.specs.pds_data_element_lengths()) {
+                Ok(()) => {
+                    let pds_data_elements = self.specs.pds_data_elements.iter();
+                    for pds_data_element in pds_data_elements.filter_map(|label_id| DataElements::field_number(label_id)) {
+                        let additional_data = pds.encode(pds_data_element);
+                        if additional_data.is_empty() {
+                            continue;
+                        }
+                        if data_elements.contains_de(pds_data_element) {
+                            errors.push(format!("DE {} can't be set along with pds", pds_data_element));
+                        }
+                        data_elements.set_de(pds_data_element, IPMValue::String(additional_data));
+                    }
+                }
+                Err(error) => errors.push(error.to_string()),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(eyre!("unable to build message: {}", errors.join(", ")));
+        }
+
+        let bitmap = writer::compute_bitmap_for(self.specs, data_elements.numbers())?;
+        let (primary_bitmap, secondary_bitmap, tertiary_bitmap) = self.specs.bitmap_layout.split(&bitmap);
+
+        if let Some(secondary_bitmap) = secondary_bitmap {
+            data_elements.set_de(1, IPMValue::Binary(secondary_bitmap.to_vec()));
+        }
+        if let Some(tertiary_bitmap) = tertiary_bitmap {
+            data_elements.set_de(65, IPMValue::Binary(tertiary_bitmap.to_vec()));
+        }
+
+        Ok(Message {
+            category: Message::get_category(self.specs.profile, &mti, data_elements.de(24)),
+            mti,
+            primary_bitmap,
+            data_elements,
+            pds,
+            raw: None,
+        })
+    }
+
+    fn field_spec_label_id(&self, field_number: usize) -> Result<String> {
+        if field_number == 1 {
+            return Err(eyre!("DE 1 is the secondary bitmap, which is computed on build"));
+        }
+
+        let label_id = format!("{:03}", field_number);
+        match self.specs.field(&label_id) {
+            Some(_) => Ok(label_id),
+            None => Err(eyre!("no spec found for data element {}", field_number)),
+        }
+    }
+
+    fn ipm_value(&self, field_number: usize, value: &str) -> Result<IPMValue> {
+        let label_id = self.field_spec_label_id(field_number)?;
+        let iso_field = self.specs.field(&label_id).expect("spec checked on label id");
+
+        if value.len() > iso_field.length {
+            return Err(eyre!("{} has {} characters, max is {}", iso_field.label, value.len(), iso_field.length));
+        }
+
+        if !iso_field.char_type.accepts(value) {
+            return Err(eyre!("{} should be {:?}: {:?}", iso_field.label, iso_field.char_type.as_str(), value));
+        }
+
+        match iso_field.char_type.get_str("content_type") {
+            Some("number") if iso_field.size_type == FieldSizeType::Fixed => Ok(IPMValue::u64(value.parse::<u64>()?)),
+            // variable numbers keep their leading zeros, as on parsing
+            Some("number") => Ok(IPMValue::String(value.to_string())),
+            Some("string") if iso_field.size_type == FieldSizeType::Fixed => {
+                // fixed values are padded, so the built message is equal to the parsed one after encoding
+                let padded = writer::pad_content(iso_field, &IPMValue::String(value.to_string()))?;
+                Ok(IPMValue::String(String::from_utf8_lossy(&padded).to_string()))
+            }
+            Some("string") => Ok(IPMValue::String(value.to_string())),
+            _ => Ok(IPMValue::Binary(value.as_bytes().to_vec())),
+        }
+    }
+}
+
+#[test]
+fn test_built_message_is_equal_to_its_parsed_encoding() {
+    let specs = IsoSpecs::new();
+
+    let message = MessageBuilder::new(&specs)
+        .mti("1240")
+        .de(2, "5499999999999998")
+        .de(3, "000000")
+        .de(4, "1500")
+        .de(24, "200")
+        .de(43, "MERCHANT\\SAO PAULO\\")
+        .de(71, "7")
+        .de(94, "23379")
+        .pds("0023", "CT6")
+        .pds("0158", "          IV")
+        .build()
+        .unwrap();
+
+    assert_eq!(message.category.get_str("name"), Some("first_presentments"));
+    assert_eq!(message.primary_bitmap, [0xF0, 0, 1, 0, 0, 0x21, 0, 0]);
+    assert_eq!(message.data_elements["001"].get_string(), format!("{:?}", [2u8, 0, 0, 4, 0, 0, 0, 0]));
+
+    let encoded = writer::encode_message(&specs, &message).unwrap();
+    let parsed = crate::parse_file(encoded).unwrap();
+
+    let parsed_message = &parsed.messages[0];
+    assert_eq!(parsed_message.primary_bitmap, message.primary_bitmap);
+    assert_eq!(parsed_message.pds, message.pds);
+    for (field_number, value) in message.data_elements.iter() {
+        assert_eq!(parsed_message.data_elements[field_number].get_string(), value.get_string());
+    }
+}
+
+#[test]
+fn test_variable_numbers_keep_their_leading_zeros() {
+    let specs = IsoSpecs::new();
+
+    let message = MessageBuilder::new(&specs).mti("1240").de(33, "0012345").build().unwrap();
+    let encoded = writer::encode_message(&specs, &message).unwrap();
+    assert!(encoded.ends_with(b"070012345"));
+
+    let parsed = crate::parse_file(encoded).unwrap();
+    assert_eq!(parsed.messages[0].data_elements[33], IPMValue::String("0012345".to_string()));
+}
+
+#[test]
+fn test_fixed_fields_are_padded_before_their_encoding() {
+    use crate::iso_field::FieldEncoding;
+
+    let mut specs = IsoSpecs::iso8583_1987();
+    specs.specs[41].content_encoding = FieldEncoding::Ebcdic;
+
+    let message = MessageBuilder::new(&specs)
+        .mti("0100")
+        .de(3, "000000")
+        .de(4, "1500")
+        .de(11, "000001")
+        .de(41, "TERM01")
+        .build()
+        .unwrap();
+    assert_eq!(message.data_elements[41], IPMValue::String("TERM01  ".to_string()));
+
+    let encoded = writer::encode_message(&specs, &message).unwrap();
+    assert!(encoded.ends_with(&[0xE3, 0xC5, 0xD9, 0xD4, 0xF0, 0xF1, 0x40, 0x40]));
+
+    let parsed = crate::parse_message(&encoded, &specs).unwrap();
+    assert_eq!(parsed.data_elements[41], message.data_elements[41]);
+}
+
+#[test]
+fn test_builder_errors() {
+    let specs = IsoSpecs::new();
+
+    let result = MessageBuilder::new(&specs)
+        .de(1, "00")
+        .de(4, "12A")
+        .de(24, "2000")
+        .pds("158", "IV")
+        .build();
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("DE 1 is the secondary bitmap"));
+    assert!(error.contains("Amount, Txn should be"));
+    assert!(error.contains("Function Code has 4 characters"));
+    assert!(error.contains("invalid pds id"));
+    assert!(error.contains("mti is mandatory"));
+}
+
+#[test]
+fn test_pds_overflow_into_de_62() {
+    let specs = IsoSpecs::new();
+    let long_value = "X".repeat(600);
+
+    let message = MessageBuilder::new(&specs)
+        .mti("1240")
+        .de(24, "200")
+        .pds("0023", "POI")
+        .pds("0146", &long_value)
+        .pds("0147", &long_value)
+        .build()
+        .unwrap();
+
+    assert_eq!(message.data_elements["048"].get_string(), format!("0023003POI0146600{}", long_value));
+    assert_eq!(message.data_elements["062"].get_string(), format!("0147600{}", long_value));
+
+    let encoded = writer::encode_message(&specs, &message).unwrap();
+    let parsed = crate::parse_file(encoded).unwrap();
+    assert_eq!(parsed.messages[0].pds, message.pds);
+    assert_eq!(parsed.messages[0].pds.entry("0147").unwrap().data_element, 62);
+
+    let mut message = message;
+    message.pds.remove("0147");
+    message.distribute_pds(&specs).unwrap();
+    assert!(!message.data_elements.contains_de(62));
+
+    let additional_data = message.data_elements[48].clone();
+    let error = message.set_pds("0158", &"Y".repeat(400)).unwrap_err();
+    assert!(error.to_string().contains("pds 0158 doesn't fit on DE 48"));
+    assert_eq!(message.data_elements[48], additional_data);
+    assert!(!message.pds.contains_key("0158"));
+
+    let error = MessageBuilder::new(&specs)
+        .mti("1240")
+        .de(62, "N")
+        .pds("0146", &long_value)
+        .pds("0147", &long_value)
+        .build()
+        .unwrap_err();
+    assert!(error.to_string().contains("DE 62 can't be set along with pds"));
+
+    let pds_over_every_de = (0..6).fold(MessageBuilder::new(&specs).mti("1240"), |builder, index| {
+        builder.pds(&format!("{:04}", index), &long_value)
+    });
+    assert!(pds_over_every_de.build().unwrap_err().to_string().contains("pds 0005 doesn't fit on DE 48, 62, 123, 124, 125"));
+}
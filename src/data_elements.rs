@@ -0,0 +1,213 @@
+use crate::iso_field::IPMValue;
+use std::fmt;
+use std::ops::Index;
+
+/// Highest DE number, DEs 129 to 192 are only present along with a tertiary bitmap
+pub const MAX_DATA_ELEMENT: usize = 192;
+
+/// Data elements of a message, stored by their DE number
+///
+/// DE 1 and DE 65 hold the secondary and tertiary bitmaps. Lookups by label id (e.g `"024"`)
+/// are kept for compatibility with the specs, which identify fields by their label id
+#[derive(Clone, PartialEq)]
+pub struct DataElements {
+    slots: Box<[Option<IPMValue>]>,
+    len: usize,
+}
+
+impl Default for DataElements {
+    fn default() -> Self {
+        DataElements {
+            slots: vec![None; MAX_DATA_ELEMENT + 1].into_boxed_slice(),
+            len: 0,
+        }
+    }
+}
+
+impl DataElements {
+    pub fn new() -> DataElements {
+        DataElements::default()
+    }
+
+    /// Label id of a DE number as on the specs, e.g `"024"`
+    pub fn label_id(field_number: usize) -> String {
+        format!("{:03}", field_number)
+    }
+
+    /// DE number of a label id, `None` for the MTI, the bitmaps or any non DE label
+    pub fn field_number(label_id: &str) -> Option<usize> {
+        label_id
+            .parse::<usize>()
+            .ok()
+            .filter(|field_number| (1..=MAX_DATA_ELEMENT).contains(field_number))
+    }
+
+    pub fn de(&self, field_number: usize) -> Option<&IPMValue> {
+        self.slots.get(field_number).and_then(Option::as_ref)
+    }
+
+    pub fn contains_de(&self, field_number: usize) -> bool {
+        self.de(field_number).is_some()
+    }
+
+    /// Sets a DE, returning its previous value
+    ///
+    /// Panics when the DE number is above `MAX_DATA_ELEMENT`
+    pub fn set_de(&mut self, field_number: usize, value: IPMValue) -> Option<IPMValue> {
+        assert!(
+            (1..=MAX_DATA_ELEMENT).contains(&field_number),
+            "data element {} is out of range",
+            field_number
+        );
+
+        let previous = self.slots[field_number].replace(value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    pub fn remove_de(&mut self, field_number: usize) -> Option<IPMValue> {
+        let previous = self.slots.get_mut(field_number).and_then(Option::take);
+        if previous.is_some() {
+            self.len -= 1;
+        }
+        previous
+    }
+
+    pub fn get(&self, label_id: &str) -> Option<&IPMValue> {
+        DataElements::field_number(label_id).and_then(|field_number| self.de(field_number))
+    }
+
+    pub fn contains_key(&self, label_id: &str) -> bool {
+        self.get(label_id).is_some()
+    }
+
+    /// Sets a DE by its label id, panicking when it isn't a DE number
+    pub fn insert(&mut self, label_id: &str, value: IPMValue) -> Option<IPMValue> {
+        match DataElements::field_number(label_id) {
+            Some(field_number) => self.set_de(field_number, value),
+            None => panic!("invalid data element id {:?}", label_id),
+        }
+    }
+
+    pub fn remove(&mut self, label_id: &str) -> Option<IPMValue> {
+        DataElements::field_number(label_id).and_then(|field_number| self.remove_de(field_number))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Present DEs and their values, in DE order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &IPMValue)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(field_number, value)| value.as_ref().map(|value| (field_number, value)))
+    }
+
+    /// Present DE numbers, in DE order
+    pub fn numbers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.iter().map(|(field_number, _)| field_number)
+    }
+
+    /// Present DE label ids, in DE order
+    pub fn label_ids(&self) -> impl Iterator<Item = String> + '_ {
+        self.numbers().map(DataElements::label_id)
+    }
+}
+
+impl Index<&str> for DataElements {
+    type Output = IPMValue;
+
+    fn index(&self, label_id: &str) -> &IPMValue {
+        self.get(label_id)
+            .unwrap_or_else(|| panic!("data element {:?} not present", label_id))
+    }
+}
+
+impl Index<usize> for DataElements {
+    type Output = IPMValue;
+
+    fn index(&self, field_number: usize) -> &IPMValue {
+        self.de(field_number)
+            .unwrap_or_else(|| panic!("data element {} not present", field_number))
+    }
+}
+
+impl FromIterator<(usize, IPMValue)> for DataElements {
+    fn from_iter<I: IntoIterator<Item = (usize, IPMValue)>>(iter: I) -> Self {
+        let mut data_elements = DataElements::new();
+        for (field_number, value) in iter {
+            data_elements.set_de(field_number, value);
+        }
+        data_elements
+    }
+}
+
+impl<const N: usize> From<[(usize, IPMValue); N]> for DataElements {
+    fn from(values: [(usize, IPMValue); N]) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl IntoIterator for DataElements {
+    type Item = (usize, IPMValue);
+    type IntoIter = Box<dyn Iterator<Item = (usize, IPMValue)>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(
+            self.slots
+                .into_vec()
+                .into_iter()
+                .enumerate()
+                .filter_map(|(field_number, value)| value.map(|value| (field_number, value))),
+        )
+    }
+}
+
+impl fmt::Debug for DataElements {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(field_number, value)| (DataElements::label_id(field_number), value)))
+            .finish()
+    }
+}
+
+/// Serialized as a map keyed by label id, in DE order
+impl serde::Serialize for DataElements {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(self.iter().map(|(field_number, value)| (DataElements::label_id(field_number), value)))
+    }
+}
+
+#[test]
+fn test_data_elements_slots() {
+    let mut data_elements = DataElements::from([
+        (71, IPMValue::u64(1)),
+        (24, IPMValue::String("200".to_string())),
+        (2, IPMValue::String("5499999999999998".to_string())),
+    ]);
+
+    assert_eq!(data_elements.len(), 3);
+    assert_eq!(data_elements.de(24).unwrap().get_string(), "200");
+    assert_eq!(data_elements["024"].get_string(), "200");
+    assert_eq!(data_elements[71].get_string(), "1");
+    assert!(data_elements.get("mti").is_none());
+    assert!(data_elements.get("Function Code").is_none());
+    assert_eq!(data_elements.numbers().collect::<Vec<_>>(), vec![2, 24, 71]);
+
+    assert!(data_elements.insert("024", IPMValue::String("205".to_string())).is_some());
+    assert_eq!(data_elements.len(), 3);
+    assert!(data_elements.remove_de(2).is_some());
+    assert!(data_elements.remove("002").is_none());
+    assert_eq!(data_elements.label_ids().collect::<Vec<_>>(), vec!["024", "071"]);
+}
@@ -0,0 +1,354 @@
+use eyre::{eyre, Result};
+
+const PAN_MIN_LEN: usize = 12;
+const PAN_MAX_LEN: usize = 19;
+const ARN_LEN: usize = 23;
+const PAN_MASK_CHAR: char = '*';
+const INSTALLMENT_MIN_LEN: usize = 33;
+const FILE_ID_LEN: usize = 25;
+
+/// Primary Account Number (DE 2)
+///
+/// Clearing files may carry masked PANs (e.g `999999******9999`), which is the reason DE 2 is
+/// specified as `ns` instead of `n`. A masked PAN can be decoded, but it can't have its check digit
+/// verified.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Pan {
+    pub value: String,
+    pub masked: bool,
+}
+
+impl Pan {
+    pub fn decode(value: &str) -> Result<Pan> {
+        let value = value.trim();
+
+        if !(PAN_MIN_LEN..=PAN_MAX_LEN).contains(&value.len()) {
+            return Err(eyre!("invalid pan length {} for {:?}", value.len(), value));
+        }
+
+        if !value.chars().all(|c| c.is_ascii_digit() || c == PAN_MASK_CHAR) {
+            return Err(eyre!("pan should contain only digits or masking characters: {:?}", value));
+        }
+
+        Ok(Pan {
+            value: value.to_string(),
+            masked: value.contains(PAN_MASK_CHAR),
+        })
+    }
+
+    /// The first six digits of the PAN, available even on masked PANs
+    pub fn bin(&self) -> Option<&str> {
+        let bin = &self.value[0..6];
+        bin.chars().all(|c| c.is_ascii_digit()).then_some(bin)
+    }
+
+    /// Returns `None` for masked PANs, since their check digit can't be verified
+    pub fn has_valid_check_digit(&self) -> Option<bool> {
+        if self.masked {
+            None
+        } else {
+            Some(luhn_check(&self.value))
+        }
+    }
+}
+
+/// Acquirer Reference Number (DE 31)
+///
+/// The 23 digits are split as (M)(BBBBBB)(YDDD)(SSSSSSSSSSS)(C):
+/// mixed digit, acquirer BIN, julian processing date, sequence number and a Luhn check digit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Arn {
+    pub value: String,
+    pub mixed_digit: u8,
+    pub acquirer_bin: String,
+    pub julian_date: String,
+    pub sequence_number: String,
+    pub check_digit: u8,
+}
+
+impl Arn {
+    pub fn decode(value: &str) -> Result<Arn> {
+        if value.len() != ARN_LEN {
+            return Err(eyre!("invalid arn length {} for {:?}", value.len(), value));
+        }
+
+        if !value.chars().all(|c| c.is_ascii_digit()) {
+            return Err(eyre!("arn should contain only digits: {:?}", value));
+        }
+
+        let day_of_year = value[8..11].parse::<u16>()?;
+        if !(1..=366).contains(&day_of_year) {
+            return Err(eyre!("invalid julian date {:?} on arn {:?}", &value[7..11], value));
+        }
+
+        let digits = value.as_bytes();
+
+        Ok(Arn {
+            value: value.to_string(),
+            mixed_digit: digits[0] - b'0',
+            acquirer_bin: value[1..7].to_string(),
+            julian_date: value[7..11].to_string(),
+            sequence_number: value[11..22].to_string(),
+            check_digit: digits[22] - b'0',
+        })
+    }
+
+    /// Last digit of the year in which the transaction was processed
+    pub fn year_digit(&self) -> u8 {
+        self.julian_date.as_bytes()[0] - b'0'
+    }
+
+    pub fn day_of_year(&self) -> u16 {
+        // already checked on decoding
+        self.julian_date[1..].parse::<u16>().unwrap_or_default()
+    }
+
+    pub fn has_valid_check_digit(&self) -> bool {
+        luhn_check(&self.value)
+    }
+}
+
+/// File ID (PDS 0105), carried by the header and trailer of IPM files
+///
+/// The 25 digits are split as (TTT)(YYMMDD)(PPPPPPPPPPP)(SSSSS):
+/// file type, file reference date, processor ID and file sequence number
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileId {
+    pub value: String,
+    pub file_type: String,
+    pub file_reference_date: String,
+    pub processor_id: String,
+    pub file_sequence_number: String,
+}
+
+impl FileId {
+    pub fn decode(value: &str) -> Result<FileId> {
+        if value.len() != FILE_ID_LEN || !value.chars().all(|c| c.is_ascii_digit()) {
+            return Err(eyre!("file id should have {} digits: {:?}", FILE_ID_LEN, value));
+        }
+
+        let month = value[5..7].parse::<u8>()?;
+        let day = value[7..9].parse::<u8>()?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(eyre!("invalid file reference date {:?} on file id {:?}", &value[3..9], value));
+        }
+
+        Ok(FileId {
+            value: value.to_string(),
+            file_type: value[0..3].to_string(),
+            file_reference_date: value[3..9].to_string(),
+            processor_id: value[9..20].to_string(),
+            file_sequence_number: value[20..25].to_string(),
+        })
+    }
+
+    /// Same file ID with another file sequence number, e.g for the files resulting from a split
+    pub fn with_file_sequence_number(&self, file_sequence_number: u32) -> Result<FileId> {
+        if file_sequence_number > 99_999 {
+            return Err(eyre!("file sequence number {} exceeds 5 digits", file_sequence_number));
+        }
+
+        FileId::decode(&format!(
+            "{}{}{}{:05}",
+            self.file_type, self.file_reference_date, self.processor_id, file_sequence_number
+        ))
+    }
+}
+
+/// Installment Payment Data (PDS 0181), the Brazilian parcelado
+///
+/// Subfields are (TT)(NN)(RRRRR)(FFFFFFFFFFFF)(SSSSSSSSSSSS) followed by the optional
+/// (AAAAA)(EEEEEEEEEEEE)(CCCCC): plan type, number of installments, interest rate, first and
+/// subsequent installment amounts, annual percentage rate, installment fee and total cost rate.
+/// Rates are in hundredths of a percent and amounts in minor units, as DE 4.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Installment {
+    pub value: String,
+    pub plan_type: String,
+    pub number_of_installments: u8,
+    pub interest_rate: u32,
+    pub first_installment_amount: u64,
+    pub installment_amount: u64,
+    pub annual_percentage_rate: Option<u32>,
+    pub installment_fee: Option<u64>,
+    pub total_cost_rate: Option<u32>,
+}
+
+impl Installment {
+    pub fn decode(value: &str) -> Result<Installment> {
+        if value.len() < INSTALLMENT_MIN_LEN || !value.is_ascii() {
+            return Err(eyre!("invalid installment data {:?}", value));
+        }
+
+        let number = |start: usize, end: usize| -> Result<Option<u64>> {
+            if start >= value.len() {
+                return Ok(None);
+            }
+            match value.get(start..end) {
+                Some(digits) if digits.trim().is_empty() => Ok(None),
+                Some(digits) => digits
+                    .parse::<u64>()
+                    .map(Some)
+                    .map_err(|_| eyre!("installment data should be numeric at {}: {:?}", start + 1, value)),
+                None => Err(eyre!("truncated installment subfield at {}: {:?}", start + 1, value)),
+            }
+        };
+        let mandatory = |start: usize, end: usize| -> Result<u64> {
+            number(start, end)?.ok_or_else(|| eyre!("missing installment subfield at {}: {:?}", start + 1, value))
+        };
+
+        let number_of_installments = mandatory(2, 4)? as u8;
+        if number_of_installments < 2 {
+            return Err(eyre!("installments should be at least 2: {:?}", value));
+        }
+
+        Ok(Installment {
+            value: value.to_string(),
+            plan_type: value[0..2].to_string(),
+            number_of_installments,
+            interest_rate: mandatory(4, 9)? as u32,
+            first_installment_amount: mandatory(9, 21)?,
+            installment_amount: mandatory(21, 33)?,
+            annual_percentage_rate: number(33, 38)?.map(|rate| rate as u32),
+            installment_fee: number(38, 50)?,
+            total_cost_rate: number(50, 55)?.map(|rate| rate as u32),
+        })
+    }
+
+    /// Sum of the first and the subsequent installments
+    pub fn total_amount(&self) -> u64 {
+        self.first_installment_amount + self.installment_amount * u64::from(self.number_of_installments - 1)
+    }
+
+    /// Checks the installments against the transaction amount (DE 4), which may only be
+    /// exceeded when there is interest
+    pub fn matches_amount(&self, transaction_amount: u64) -> bool {
+        if self.interest_rate == 0 {
+            self.total_amount() == transaction_amount
+        } else {
+            self.total_amount() >= transaction_amount
+        }
+    }
+}
+
+/// Computes the Luhn (mod 10) check digit for a digits only payload
+pub fn luhn_check_digit(payload: &str) -> Option<u8> {
+    let mut sum = 0u32;
+
+    for (position, c) in payload.chars().rev().enumerate() {
+        let mut digit = c.to_digit(10)?;
+
+        if position % 2 == 0 {
+            digit *= 2;
+            if digit > 9 {
+                digit -= 9;
+            }
+        }
+
+        sum += digit;
+    }
+
+    Some(((10 - sum % 10) % 10) as u8)
+}
+
+/// Checks if the last digit of `value` is the Luhn check digit of the preceding ones
+pub fn luhn_check(value: &str) -> bool {
+    match value.char_indices().last() {
+        Some((last_position, last_char)) if last_position > 0 => {
+            match (luhn_check_digit(&value[..last_position]), last_char.to_digit(10)) {
+                (Some(expected), Some(found)) => u32::from(expected) == found,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+#[test]
+fn test_luhn_check() {
+    assert!(luhn_check("4111111111111111"));
+    assert!(luhn_check("5499999999999998"));
+    assert!(!luhn_check("5499999999999999"));
+    assert!(!luhn_check("54999999999999A8"));
+    assert!(!luhn_check("4"));
+}
+
+#[test]
+fn test_masked_pan_skips_check_digit() {
+    let pan = Pan::decode("999999******9999").unwrap();
+
+    assert!(pan.masked);
+    assert_eq!(pan.bin(), Some("999999"));
+    assert_eq!(pan.has_valid_check_digit(), None);
+
+    let pan = Pan::decode("5499999999999999").unwrap();
+    assert_eq!(pan.has_valid_check_digit(), Some(false));
+
+    assert!(Pan::decode("54999999X9999999").is_err());
+}
+
+#[test]
+fn test_arn_decoding() {
+    let arn = Arn::decode("75123453123000000000015").unwrap();
+
+    assert_eq!(arn.mixed_digit, 7);
+    assert_eq!(arn.acquirer_bin, "512345");
+    assert_eq!(arn.julian_date, "3123");
+    assert_eq!(arn.year_digit(), 3);
+    assert_eq!(arn.day_of_year(), 123);
+    assert_eq!(arn.sequence_number, "00000000001");
+    assert!(arn.has_valid_check_digit());
+
+    assert!(!Arn::decode("75123453123000000000016").unwrap().has_valid_check_digit());
+    assert!(Arn::decode("75123453400000000000015").is_err());
+    assert!(Arn::decode("7512345312300000000001").is_err());
+}
+
+#[test]
+fn test_installment_decoding() {
+    let installment = Installment::decode("2103000000000000000340000000000").unwrap_err();
+    assert!(installment.to_string().contains("invalid installment data"));
+
+    let installment = Installment::decode("210300000000000000034000000000033").unwrap();
+    assert_eq!(installment.plan_type, "21");
+    assert_eq!(installment.number_of_installments, 3);
+    assert_eq!(installment.interest_rate, 0);
+    assert_eq!(installment.first_installment_amount, 34);
+    assert_eq!(installment.installment_amount, 33);
+    assert_eq!(installment.annual_percentage_rate, None);
+    assert_eq!(installment.total_amount(), 100);
+    assert!(installment.matches_amount(100));
+    assert!(!installment.matches_amount(99));
+
+    let installment = Installment::decode("2010001990000000001000000000001000239000000000015002500").unwrap();
+    assert_eq!(installment.number_of_installments, 10);
+    assert_eq!(installment.interest_rate, 199);
+    assert_eq!(installment.annual_percentage_rate, Some(2390));
+    assert_eq!(installment.installment_fee, Some(150));
+    assert_eq!(installment.total_cost_rate, Some(2500));
+    assert!(installment.matches_amount(950));
+
+    assert!(Installment::decode("210100000000000000034000000000033").is_err());
+    assert!(Installment::decode("2103000000000000000340000000000AB").is_err());
+
+    let installment = Installment::decode("20100019900000000010000000000010002").unwrap_err();
+    assert!(installment.to_string().contains("truncated installment subfield"));
+}
+
+#[test]
+fn test_file_id_decoding() {
+    let file_id = FileId::decode("0032105270000002337901101").unwrap();
+
+    assert_eq!(file_id.file_type, "003");
+    assert_eq!(file_id.file_reference_date, "210527");
+    assert_eq!(file_id.processor_id, "00000023379");
+    assert_eq!(file_id.file_sequence_number, "01101");
+
+    assert!(FileId::decode("003210527000000233790110").is_err());
+    assert!(FileId::decode("0032113270000002337901101").is_err());
+    assert!(FileId::decode("00321052700000023379011A1").is_err());
+
+    let next_file_id = file_id.with_file_sequence_number(1102).unwrap();
+    assert_eq!(next_file_id.value, "0032105270000002337901102");
+    assert!(file_id.with_file_sequence_number(100_000).is_err());
+}
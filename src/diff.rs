@@ -0,0 +1,276 @@
+use crate::data_elements::DataElements;
+use crate::iso_specs::IsoSpecs;
+use crate::query::Selector;
+use crate::{Iso8583File, Message};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+/// How messages of both files are paired before being compared
+#[derive(Debug, Clone)]
+pub enum Alignment {
+    /// n-th message against n-th message
+    Position,
+    /// messages sharing the same values, e.g DE 31 + DE 71, in their order of appearance
+    Key(Vec<Selector>),
+}
+
+/// A DE (label id, e.g `031`), a PDS (e.g `0158`) or the `mti` whose value differs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldDiff {
+    pub field_id: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum MessageDiff {
+    Removed {
+        left_index: usize,
+        key: Option<String>,
+    },
+    Added {
+        right_index: usize,
+        key: Option<String>,
+    },
+    Changed {
+        left_index: usize,
+        right_index: usize,
+        key: Option<String>,
+        fields: Vec<FieldDiff>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileDiff {
+    pub messages: Vec<MessageDiff>,
+}
+
+impl FileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+fn message_key(selectors: &[Selector], message: &Message) -> String {
+    selectors
+        .iter()
+        .map(|selector| selector.value(message).unwrap_or_default())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Compares two messages, DE 48 being compared through its PDS and bitmaps through the DEs they flag
+pub fn diff_messages(left: &Message, right: &Message) -> Vec<FieldDiff> {
+    diff_messages_with_specs(left, right, &IsoSpecs::new())
+}
+
+/// Same as `diff_messages`, the PDS data elements of the specs (DE 48, 62, 123, 124 and 125 on IPM
+/// messages) being compared through their PDS
+pub fn diff_messages_with_specs(left: &Message, right: &Message, specs: &IsoSpecs) -> Vec<FieldDiff> {
+    let mut fields = vec![];
+
+    if left.mti != right.mti {
+        fields.push(FieldDiff {
+            field_id: "mti".to_string(),
+            left: Some(left.mti.clone()),
+            right: Some(right.mti.clone()),
+        });
+    }
+
+    let field_numbers: BTreeSet<usize> = left.data_elements.numbers().chain(right.data_elements.numbers()).collect();
+    let compare_pds = !left.pds.is_empty() || !right.pds.is_empty();
+    let pds_data_elements: Vec<usize> = specs
+        .pds_data_elements
+        .iter()
+        .filter_map(|label_id| DataElements::field_number(label_id))
+        .collect();
+
+    for field_number in field_numbers {
+        if field_number == 1 || field_number == 65 || (compare_pds && pds_data_elements.contains(&field_number)) {
+            continue;
+        }
+
+        let left_value = left.de(field_number).map(|value| value.get_string());
+        let right_value = right.de(field_number).map(|value| value.get_string());
+        if left_value != right_value {
+            fields.push(FieldDiff {
+                field_id: DataElements::label_id(field_number),
+                left: left_value,
+                right: right_value,
+            });
+        }
+    }
+
+    let pds_ids: BTreeSet<&String> = left.pds.keys().chain(right.pds.keys()).collect();
+    for pds_id in pds_ids {
+        let left_value = left.pds.get(pds_id);
+        let right_value = right.pds.get(pds_id);
+        if left_value != right_value {
+            fields.push(FieldDiff {
+                field_id: pds_id.clone(),
+                left: left_value.cloned(),
+                right: right_value.cloned(),
+            });
+        }
+    }
+
+    fields
+}
+
+/// Pairs the messages of both files according to the alignment, reporting removed, added and changed ones
+pub fn diff_files(left: &Iso8583File, right: &Iso8583File, alignment: &Alignment) -> FileDiff {
+    diff_files_with_specs(left, right, alignment, &IsoSpecs::new())
+}
+
+/// Same as `diff_files`, comparing the messages with `diff_messages_with_specs`
+pub fn diff_files_with_specs(
+    left: &Iso8583File,
+    right: &Iso8583File,
+    alignment: &Alignment,
+    specs: &IsoSpecs,
+) -> FileDiff {
+    let pairs: Vec<(Option<usize>, Option<usize>, Option<String>)> = match alignment {
+        Alignment::Position => (0..left.messages.len().max(right.messages.len()))
+            .map(|index| {
+                let exists = |file: &Iso8583File| Some(index).filter(|index| *index < file.messages.len());
+                (exists(left), exists(right), None)
+            })
+            .collect(),
+        Alignment::Key(selectors) => {
+            let mut right_by_key: HashMap<String, VecDeque<usize>> = HashMap::new();
+            for (index, message) in right.messages.iter().enumerate() {
+                right_by_key.entry(message_key(selectors, message)).or_default().push_back(index);
+            }
+
+            let mut pairs = vec![];
+            for (left_index, message) in left.messages.iter().enumerate() {
+                let key = message_key(selectors, message);
+                let right_index = right_by_key.get_mut(&key).and_then(VecDeque::pop_front);
+                pairs.push((Some(left_index), right_index, Some(key)));
+            }
+
+            let mut unmatched: Vec<(usize, String)> = right_by_key
+                .into_iter()
+                .flat_map(|(key, indexes)| indexes.into_iter().map(move |index| (index, key.clone())))
+                .collect();
+            unmatched.sort();
+            pairs.extend(unmatched.into_iter().map(|(index, key)| (None, Some(index), Some(key))));
+
+            pairs
+        }
+    };
+
+    let messages = pairs
+        .into_iter()
+        .filter_map(|(left_index, right_index, key)| match (left_index, right_index) {
+            (Some(left_index), Some(right_index)) => {
+                let fields = diff_messages_with_specs(&left.messages[left_index], &right.messages[right_index], specs);
+                (!fields.is_empty()).then_some(MessageDiff::Changed {
+                    left_index,
+                    right_index,
+                    key,
+                    fields,
+                })
+            }
+            (Some(left_index), None) => Some(MessageDiff::Removed { left_index, key }),
+            (None, Some(right_index)) => Some(MessageDiff::Added { right_index, key }),
+            (None, None) => None,
+        })
+        .collect();
+
+    FileDiff { messages }
+}
+
+fn display_value(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("{:?}", value),
+        None => "(missing)".to_string(),
+    }
+}
+
+fn display_key(key: &Option<String>) -> String {
+    match key {
+        Some(key) => format!(" [{}]", key),
+        None => String::new(),
+    }
+}
+
+/// One line per removed (`-`) or added (`+`) message, changed ones (`~`) followed by their fields
+impl fmt::Display for FileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for message in self.messages.iter() {
+            match message {
+                MessageDiff::Removed { left_index, key } => {
+                    writeln!(f, "- message {}{}", left_index, display_key(key))?;
+                }
+                MessageDiff::Added { right_index, key } => {
+                    writeln!(f, "+ message {}{}", right_index, display_key(key))?;
+                }
+                MessageDiff::Changed {
+                    left_index,
+                    right_index,
+                    key,
+                    fields,
+                } => {
+                    writeln!(f, "~ message {} -> {}{}", left_index, right_index, display_key(key))?;
+                    for field in fields {
+                        writeln!(
+                            f,
+                            "    {}: {} -> {}",
+                            field.field_id,
+                            display_value(&field.left),
+                            display_value(&field.right)
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_diff_messages() {
+    use crate::builder::MessageBuilder;
+    use crate::iso_specs::IsoSpecs;
+
+    let specs = IsoSpecs::new();
+    let presentment = |amount: &str, business_activity: &str| {
+        MessageBuilder::new(&specs)
+            .mti("1240")
+            .de(4, amount)
+            .de(24, "200")
+            .pds("0158", business_activity)
+            .build()
+            .unwrap()
+    };
+
+    let fields = diff_messages(&presentment("100", "MCC"), &presentment("150", "MCC"));
+    assert_eq!(
+        fields,
+        vec![FieldDiff {
+            field_id: "004".to_string(),
+            left: Some("100".to_string()),
+            right: Some("150".to_string()),
+        }]
+    );
+
+    let fields = diff_messages(&presentment("100", "MCC"), &presentment("100", "DMC"));
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0].field_id, "0158");
+
+    // PDS overflowing into DE 62 are only reported as PDS
+    let overflowing = |value: &str| {
+        MessageBuilder::new(&specs)
+            .mti("1240")
+            .pds("0146", &"X".repeat(990))
+            .pds("0158", value)
+            .build()
+            .unwrap()
+    };
+    let (left, right) = (overflowing("MCC"), overflowing("DMC"));
+    assert!(right.data_elements.contains_de(62));
+    let fields = diff_messages_with_specs(&left, &right, &specs);
+    assert_eq!(fields.iter().map(|field| field.field_id.as_str()).collect::<Vec<_>>(), vec!["0158"]);
+}
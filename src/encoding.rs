@@ -0,0 +1,178 @@
+use crate::iso_field::FieldEncoding;
+use eyre::{eyre, Result};
+
+const EBCDIC_UNKNOWN: u8 = 0x6F; // `?`
+const ASCII_UNKNOWN: u8 = b'?';
+
+/// Printable ASCII characters (from 0x20 to 0x7E) and their EBCDIC (CP037) counterparts
+const ASCII_TO_EBCDIC: [u8; 95] = [
+    0x40, 0x5A, 0x7F, 0x7B, 0x5B, 0x6C, 0x50, 0x7D, 0x4D, 0x5D, 0x5C, 0x4E, 0x6B, 0x60, 0x4B, 0x61, // ` ` to `/`
+    0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, // `0` to `9`
+    0x7A, 0x5E, 0x4C, 0x7E, 0x6E, 0x6F, 0x7C, // `:` to `@`
+    0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, // `A` to `I`
+    0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, // `J` to `R`
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, // `S` to `Z`
+    0xBA, 0xE0, 0xBB, 0xB0, 0x6D, 0x79, // `[` to `` ` ``
+    0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, // `a` to `i`
+    0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, // `j` to `r`
+    0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, // `s` to `z`
+    0xC0, 0x4F, 0xD0, 0xA1, // `{` to `~`
+];
+
+pub fn ascii_to_ebcdic(ascii: &[u8]) -> Vec<u8> {
+    ascii
+        .iter()
+        .map(|&c| match c {
+            0x20..=0x7E => ASCII_TO_EBCDIC[usize::from(c - 0x20)],
+            _ => EBCDIC_UNKNOWN,
+        })
+        .collect()
+}
+
+pub fn ebcdic_to_ascii(ebcdic: &[u8]) -> Vec<u8> {
+    ebcdic
+        .iter()
+        .map(|c| match ASCII_TO_EBCDIC.iter().position(|e| e == c) {
+            Some(position) => position as u8 + 0x20,
+            None => ASCII_UNKNOWN,
+        })
+        .collect()
+}
+
+/// Packs a digits string into BCD, padding odd lengths with a zero nibble on the left,
+/// or with an `F` nibble on the right
+pub fn bcd_encode(digits: &[u8], left_padded: bool) -> Result<Vec<u8>> {
+    let mut nibbles = digits
+        .iter()
+        .map(|digit| match digit {
+            b'0'..=b'9' => Ok(digit - b'0'),
+            _ => Err(eyre!("bcd only accepts digits: {:?}", String::from_utf8_lossy(digits))),
+        })
+        .collect::<Result<Vec<u8>>>()?;
+
+    if nibbles.len() % 2 == 1 {
+        if left_padded {
+            nibbles.insert(0, 0);
+        } else {
+            nibbles.push(0x0F);
+        }
+    }
+
+    Ok(nibbles.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect())
+}
+
+/// Unpacks `digits_count` digits from BCD, skipping the padding nibble
+pub fn bcd_decode(packed: &[u8], digits_count: usize, left_padded: bool) -> Result<Vec<u8>> {
+    let nibbles: Vec<u8> = packed.iter().flat_map(|byte| [byte >> 4, byte & 0x0F]).collect();
+
+    let padding = nibbles.len().saturating_sub(digits_count);
+    let digits = if left_padded {
+        &nibbles[padding..]
+    } else {
+        &nibbles[..nibbles.len() - padding]
+    };
+
+    digits
+        .iter()
+        .map(|&nibble| match nibble {
+            0..=9 => Ok(nibble + b'0'),
+            _ => Err(eyre!("invalid bcd nibble {:X} on {:02X?}", nibble, packed)),
+        })
+        .collect()
+}
+
+/// Number of bytes needed to encode `content_length` characters (or digits, for BCD)
+pub fn encoded_size(encoding: FieldEncoding, content_length: usize) -> usize {
+    match encoding {
+        FieldEncoding::BcdLeftPadded | FieldEncoding::BcdRightPadded => (content_length + 1) / 2,
+        FieldEncoding::Ascii | FieldEncoding::Ebcdic | FieldEncoding::Binary => content_length,
+    }
+}
+
+/// Size in bytes of a length prefix holding up to `digits` decimal digits, e.g 2 for LLVar
+pub fn prefix_size(encoding: FieldEncoding, digits: usize) -> usize {
+    match encoding {
+        FieldEncoding::Binary => (digits + 1) / 2,
+        _ => encoded_size(encoding, digits),
+    }
+}
+
+/// Encodes ASCII content into the field encoding
+pub fn encode_content(encoding: FieldEncoding, content: &[u8]) -> Result<Vec<u8>> {
+    match encoding {
+        FieldEncoding::Ascii | FieldEncoding::Binary => Ok(content.to_vec()),
+        FieldEncoding::Ebcdic => Ok(ascii_to_ebcdic(content)),
+        FieldEncoding::BcdLeftPadded => bcd_encode(content, true),
+        FieldEncoding::BcdRightPadded => bcd_encode(content, false),
+    }
+}
+
+/// Decodes the field content back into ASCII
+pub fn decode_content(encoding: FieldEncoding, encoded: &[u8], content_length: usize) -> Result<Vec<u8>> {
+    match encoding {
+        FieldEncoding::Ascii | FieldEncoding::Binary => Ok(encoded.to_vec()),
+        FieldEncoding::Ebcdic => Ok(ebcdic_to_ascii(encoded)),
+        FieldEncoding::BcdLeftPadded => bcd_decode(encoded, content_length, true),
+        FieldEncoding::BcdRightPadded => bcd_decode(encoded, content_length, false),
+    }
+}
+
+pub fn encode_length_prefix(encoding: FieldEncoding, content_length: usize, digits: usize) -> Result<Vec<u8>> {
+    let decimal = format!("{:0w$}", content_length, w = digits);
+    if decimal.len() > digits {
+        return Err(eyre!("length {} doesn't fit into {} digits", content_length, digits));
+    }
+
+    match encoding {
+        FieldEncoding::Binary => {
+            let size = prefix_size(encoding, digits);
+            Ok(content_length.to_be_bytes()[std::mem::size_of::<usize>() - size..].to_vec())
+        }
+        _ => encode_content(encoding, decimal.as_bytes()),
+    }
+}
+
+pub fn decode_length_prefix(encoding: FieldEncoding, prefix: &[u8], digits: usize) -> Result<usize> {
+    match encoding {
+        FieldEncoding::Binary => Ok(prefix.iter().fold(0usize, |length, &byte| (length << 8) + usize::from(byte))),
+        _ => {
+            let decimal = decode_content(encoding, prefix, digits)?;
+            Ok(std::str::from_utf8(&decimal)?.parse::<usize>()?)
+        }
+    }
+}
+
+#[test]
+fn test_bcd_encoding() {
+    assert_eq!(bcd_encode(b"12345", true).unwrap(), vec![0x01, 0x23, 0x45]);
+    assert_eq!(bcd_encode(b"12345", false).unwrap(), vec![0x12, 0x34, 0x5F]);
+    assert!(bcd_encode(b"12A", true).is_err());
+
+    assert_eq!(bcd_decode(&[0x01, 0x23, 0x45], 5, true).unwrap(), b"12345");
+    assert_eq!(bcd_decode(&[0x12, 0x34, 0x5F], 5, false).unwrap(), b"12345");
+    assert_eq!(bcd_decode(&[0x12, 0x34], 4, true).unwrap(), b"1234");
+    assert!(bcd_decode(&[0x1A], 2, true).is_err());
+}
+
+#[test]
+fn test_ebcdic_encoding() {
+    let ascii = b"MERCHANT 01, SAO PAULO/BR".to_vec();
+    let ebcdic = ascii_to_ebcdic(&ascii);
+
+    assert_eq!(&ebcdic[0..3], &[0xD4, 0xC5, 0xD9]);
+    assert_eq!(ebcdic_to_ascii(&ebcdic), ascii);
+}
+
+#[test]
+fn test_length_prefixes() {
+    assert_eq!(encode_length_prefix(FieldEncoding::Ascii, 16, 2).unwrap(), b"16");
+    assert_eq!(encode_length_prefix(FieldEncoding::Ebcdic, 16, 2).unwrap(), vec![0xF1, 0xF6]);
+    assert_eq!(encode_length_prefix(FieldEncoding::BcdLeftPadded, 123, 3).unwrap(), vec![0x01, 0x23]);
+    assert_eq!(encode_length_prefix(FieldEncoding::Binary, 16, 2).unwrap(), vec![0x10]);
+    assert_eq!(encode_length_prefix(FieldEncoding::Binary, 999, 3).unwrap(), vec![0x03, 0xE7]);
+    assert!(encode_length_prefix(FieldEncoding::Ascii, 100, 2).is_err());
+
+    assert_eq!(decode_length_prefix(FieldEncoding::BcdLeftPadded, &[0x01, 0x23], 3).unwrap(), 123);
+    assert_eq!(decode_length_prefix(FieldEncoding::Binary, &[0x03, 0xE7], 3).unwrap(), 999);
+    assert_eq!(decode_length_prefix(FieldEncoding::Ebcdic, &[0xF1, 0xF6], 2).unwrap(), 16);
+}
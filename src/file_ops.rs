@@ -0,0 +1,237 @@
+use crate::builder::MessageBuilder;
+use crate::decoders::FileId;
+use crate::iso_field::IPMValue;
+use crate::iso_specs::{Category, IsoSpecs};
+use crate::query::Selector;
+use crate::{writer, Iso8583File, Message};
+use eyre::{eyre, Result};
+use std::collections::HashMap;
+
+/// Highest amount PDS 0301 (file amount checksum) can hold on its 16 digits
+const MAX_AMOUNT_CHECKSUM: u64 = 9_999_999_999_999_999;
+
+fn is_file_layout(message: &Message) -> bool {
+    matches!(message.category, Category::Header | Category::Trailer)
+}
+
+/// Sum of DE 4 over the messages, as carried by the trailer PDS 0301
+fn amount_checksum(messages: &[Message]) -> Result<u64> {
+    let mut checksum = 0u64;
+
+    for message in messages {
+        if let Some(amount) = message.de(4) {
+            let amount = match amount {
+                IPMValue::u64(amount) => *amount,
+                amount => amount
+                    .get_string()
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| eyre!("invalid amount {:?} on DE 4", amount.get_string()))?,
+            };
+            checksum = checksum
+                .checked_add(amount)
+                .filter(|checksum| *checksum <= MAX_AMOUNT_CHECKSUM)
+                .ok_or_else(|| eyre!("amount checksum exceeds the 16 digits of PDS 0301"))?;
+        }
+    }
+
+    Ok(checksum)
+}
+
+impl Iso8583File {
+    /// Messages other than the file header and trailer
+    pub fn body(&self) -> impl Iterator<Item = &Message> + '_ {
+        self.messages.iter().filter(|message| !is_file_layout(message))
+    }
+
+    fn first_of(&self, category_name: &str) -> Option<&Message> {
+        self.categories_indexes
+            .get(category_name)
+            .and_then(|indexes| indexes.first())
+            .map(|&index| &self.messages[index])
+    }
+
+    fn required_file_id(&self) -> Result<FileId> {
+        self.file_id()?
+            .ok_or_else(|| eyre!("the file has no header to take its file id from"))
+    }
+
+    /// Builds a file around the body messages, with DE 71 renumbered from 1 and a header and trailer
+    /// regenerated from the given ones (or from scratch), carrying the file ID, the message count
+    /// (PDS 0306) and the DE 4 checksum (PDS 0301)
+    fn with_file_layout(
+        header: Option<&Message>,
+        trailer: Option<&Message>,
+        body: Vec<Message>,
+        file_id: &FileId,
+        specs: &IsoSpecs,
+    ) -> Result<Iso8583File> {
+        let messages_count = body.len() + 2;
+        let amount_checksum = amount_checksum(&body)?;
+
+        let mut header = match header {
+            Some(header) => header.clone(),
+            None => MessageBuilder::new(specs).mti("1644").de(24, "697").de(71, "1").build()?,
+        };
+        header.set_pds("0105", &file_id.value)?;
+
+        let mut trailer = match trailer {
+            Some(trailer) => trailer.clone(),
+            None => {
+                let mut trailer = MessageBuilder::new(specs).mti("1644").de(24, "695").de(71, "1").build()?;
+                trailer.pds.insert("0301", "");
+                trailer
+            }
+        };
+        trailer.set_pds("0105", &file_id.value)?;
+        trailer.set_pds("0306", &format!("{:08}", messages_count))?;
+        if trailer.pds.contains_key("0301") {
+            trailer.set_pds("0301", &format!("{:016}", amount_checksum))?;
+        }
+
+        let mut messages = Vec::with_capacity(messages_count);
+        messages.push(header);
+        messages.extend(body);
+        messages.push(trailer);
+
+        for (index, message) in messages.iter_mut().enumerate() {
+            message.data_elements.set_de(71, IPMValue::u64(index as u64 + 1));
+        }
+
+        Iso8583File::new(messages)
+    }
+
+    fn split_into(&self, groups: Vec<Vec<Message>>, specs: &IsoSpecs) -> Result<Vec<Iso8583File>> {
+        let file_id = self.required_file_id()?;
+        let first_sequence_number = file_id.file_sequence_number.parse::<u32>()?;
+
+        groups
+            .into_iter()
+            .enumerate()
+            .map(|(index, body)| {
+                let file_id = file_id.with_file_sequence_number(first_sequence_number + index as u32)?;
+                Iso8583File::with_file_layout(self.first_of("headers"), self.first_of("trailers"), body, &file_id, specs)
+            })
+            .collect()
+    }
+
+    /// Splits the body messages into files of at most `max_messages` messages each, besides their
+    /// header and trailer
+    ///
+    /// The first file keeps the file ID of the original one, the next ones increment its file
+    /// sequence number. Regenerated headers and trailers are built with the given specs
+    pub fn split_by_count(&self, max_messages: usize, specs: &IsoSpecs) -> Result<Vec<Iso8583File>> {
+        if max_messages == 0 {
+            return Err(eyre!("files should hold at least one message"));
+        }
+
+        let body: Vec<Message> = self.body().cloned().collect();
+        let groups = match body.is_empty() {
+            true => vec![vec![]],
+            false => body.chunks(max_messages).map(<[Message]>::to_vec).collect(),
+        };
+
+        self.split_into(groups, specs)
+    }
+
+    /// Splits the body messages by the value of a DE, a PDS or their category (`Selector::Category`),
+    /// returning each value along with its file, in order of first appearance
+    ///
+    /// Messages without the DE or PDS are grouped under an empty value. File IDs are given as on
+    /// `split_by_count`
+    pub fn split_by(&self, key: &Selector, specs: &IsoSpecs) -> Result<Vec<(String, Iso8583File)>> {
+        let mut values: Vec<String> = vec![];
+        let mut groups: HashMap<String, Vec<Message>> = HashMap::new();
+
+        for message in self.body() {
+            let value = key.value(message).unwrap_or_default();
+            if !groups.contains_key(&value) {
+                values.push(value.clone());
+            }
+            groups.entry(value).or_default().push(message.clone());
+        }
+
+        let groups = values.iter().map(|value| groups.remove(value).unwrap_or_default()).collect();
+        let files = self.split_into(groups, specs)?;
+
+        Ok(values.into_iter().zip(files).collect())
+    }
+
+    /// Merges the body messages of the files, in the given order, into a file with the given ID
+    ///
+    /// Header and trailer are regenerated from the ones of the first file having them, files with
+    /// a header of another file type are refused
+    pub fn merge(files: &[Iso8583File], file_id: &FileId, specs: &IsoSpecs) -> Result<Iso8583File> {
+        for file in files {
+            if let Some(merged_file_id) = file.file_id()? {
+                if merged_file_id.file_type != file_id.file_type {
+                    return Err(eyre!(
+                        "unable to merge a file of type {} into a file of type {}",
+                        merged_file_id.file_type,
+                        file_id.file_type
+                    ));
+                }
+            }
+        }
+
+        let header = files.iter().find_map(|file| file.first_of("headers"));
+        let trailer = files.iter().find_map(|file| file.first_of("trailers"));
+        let body: Vec<Message> = files.iter().flat_map(|file| file.body().cloned()).collect();
+
+        Iso8583File::with_file_layout(header, trailer, body, file_id, specs)
+    }
+
+    /// Encodes the file through the writer with the given specs, with RDWs and optionally the 1014
+    /// blocking. Messages parsed by `parse_file_lossless` keep the bytes of their unchanged parts
+    pub fn write(&self, blocked: bool, specs: &IsoSpecs) -> Result<Vec<u8>> {
+        writer::write_file_with_specs(&self.messages, blocked, specs)
+    }
+}
+
+#[test]
+fn test_merge_regenerates_file_layout() {
+    let specs = IsoSpecs::new();
+    let presentment = |amount: &str, message_number: &str| {
+        MessageBuilder::new(&specs)
+            .mti("1240")
+            .de(4, amount)
+            .de(24, "200")
+            .de(71, message_number)
+            .pds("0023", "POI")
+            .build()
+            .unwrap()
+    };
+
+    let batch = |amounts: &[&str]| {
+        let messages = amounts
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| presentment(amount, &(index + 1).to_string()))
+            .collect();
+        Iso8583File::new(messages).unwrap()
+    };
+
+    let file_id = FileId::decode("0012303040000002337904401").unwrap();
+    let merged = Iso8583File::merge(&[batch(&["100", "250"]), batch(&["50"])], &file_id, &specs).unwrap();
+
+    assert_eq!(merged.messages.len(), 5);
+    assert_eq!(merged.file_id().unwrap(), Some(file_id));
+
+    let message_numbers: Vec<String> = merged
+        .messages
+        .iter()
+        .map(|message| message.data_elements[71].get_string())
+        .collect();
+    assert_eq!(message_numbers, vec!["1", "2", "3", "4", "5"]);
+
+    let trailer = merged.messages.last().unwrap();
+    assert_eq!(trailer.pds["0301"], "0000000000000400");
+    assert_eq!(trailer.pds["0306"], "00000005");
+    assert_eq!(trailer.data_elements["048"].get_string(), trailer.pds.encode(48));
+
+    assert!(merged.split_by_count(0, &specs).is_err());
+    let splits = merged.split_by_count(2, &specs).unwrap();
+    assert_eq!(splits.len(), 2);
+    assert_eq!(splits[1].file_id().unwrap().unwrap().file_sequence_number, "04402");
+    assert_eq!(splits[1].messages.last().unwrap().pds["0301"], "0000000000000050");
+}
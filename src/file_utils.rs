@@ -2,53 +2,100 @@ use eyre::{eyre, Result};
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io::Read;
+use std::ops::Range;
 
-/// Receives a filename string and returns the payload vec
-pub fn read_file(file_name: &str) -> Vec<u8> {
-    let mut file = File::open(file_name).expect("no file found");
-    let metadata = std::fs::metadata(file_name).expect("unable to read metadata");
+pub(crate) const RDW_SIZE: usize = 4;
+pub(crate) const BLOCK_SIZE: usize = 1014;
+pub(crate) const BLOCK_DATA_SIZE: usize = BLOCK_SIZE - 2;
+
+/// Position of each record on a payload without rdw
+pub type RecordsBoundaries = Vec<Range<usize>>;
 
-    let mut payload = vec![0; metadata.len() as usize];
+/// Receives a filename string and returns the payload vec
+pub fn read_file(file_name: &str) -> Result<Vec<u8>> {
+    let mut file = File::open(file_name).map_err(|e| eyre!("unable to open {}: {}", file_name, e))?;
+    let mut payload = vec![];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_to_end(&mut payload)?;
 
-    payload.to_vec()
+    Ok(payload)
 }
 
 /// Receives a payload and returns a cloned payload without rdw or blocking
 pub fn deblock_and_remove_rdw_from(payload: Vec<u8>) -> Result<Vec<u8>> {
-    let new_payload = if has_rdw_or_block(&payload) {
-        let mut new_vec: Vec<u8> = vec![];
-        let mut position: usize = 0;
-        let deblocked_payload = remove_blocking_chunks(payload);
-
-        //since it's possible that the rdw slice ends 4 characters (due to rdw size)
-        while let Some(calculated_rdw) = rdw_to_size(&deblocked_payload, position) {
-            position += 4;
-
-            let new_content = &deblocked_payload.get(position..(position + calculated_rdw));
-
-            new_vec.extend_from_slice(new_content.ok_or_else(|| {
-                eyre!(
-                    "unable to deblock file {:?} - position: {:?} - calculated_rdw: {:?}",
-                    new_content,
-                    position,
-                    calculated_rdw
-                )
-            })?);
-
-            position += calculated_rdw;
-        }
-
-        new_vec
-    } else {
-        payload.to_vec()
-    };
+    let (new_payload, _) = deblock_into_records(payload)?;
 
     Ok(new_payload)
 }
 
-fn remove_blocking_chunks<'a>(payload: Vec<u8>) -> Vec<u8> {
+/// Same as `deblock_and_remove_rdw_from`, also returning the records boundaries given by the RDWs,
+/// which are `None` when the payload has no rdw
+pub fn deblock_into_records(payload: Vec<u8>) -> Result<(Vec<u8>, Option<RecordsBoundaries>)> {
+    if !has_rdw_or_block(&payload) {
+        return Ok((payload, None));
+    }
+
+    let mut new_vec: Vec<u8> = vec![];
+    let mut records: RecordsBoundaries = vec![];
+    let mut position: usize = 0;
+    let deblocked_payload = remove_blocking_chunks(payload);
+
+    //since it's possible that the rdw slice ends 4 characters (due to rdw size)
+    while let Some(calculated_rdw) = rdw_to_size(&deblocked_payload, position) {
+        position += 4;
+
+        let new_content = &deblocked_payload.get(position..(position + calculated_rdw));
+
+        let record_start = new_vec.len();
+        new_vec.extend_from_slice(new_content.ok_or_else(|| {
+            eyre!(
+                "unable to deblock file {:?} - position: {:?} - calculated_rdw: {:?}",
+                new_content,
+                position,
+                calculated_rdw
+            )
+        })?);
+        records.push(record_start..new_vec.len());
+
+        position += calculated_rdw;
+    }
+
+    Ok((new_vec, Some(records)))
+}
+
+/// Prefixes each record with its RDW, followed by an empty RDW marking the end of the file
+pub fn add_rdw_to(records: &[Vec<u8>]) -> Vec<u8> {
+    let mut payload: Vec<u8> = vec![];
+
+    for record in records {
+        payload.extend_from_slice(&(record.len() as u32).to_be_bytes());
+        payload.extend_from_slice(record);
+    }
+    payload.extend_from_slice(&[0u8; RDW_SIZE]);
+
+    payload
+}
+
+/// Applies the 1014 blocking: each 1012 bytes are followed by `@@`,
+/// and the last block is filled with `@` up to 1014 bytes
+pub fn block(payload: Vec<u8>) -> Vec<u8> {
+    block_with(payload, b'@')
+}
+
+/// Same as `block`, with another padding byte, e.g `0u8` for files blocked with zeros
+pub fn block_with(payload: Vec<u8>, padding: u8) -> Vec<u8> {
+    let mut blocked_payload: Vec<u8> = Vec::with_capacity(payload.len() + payload.len() / BLOCK_DATA_SIZE * 2 + BLOCK_SIZE);
+
+    for chunk in payload.chunks(BLOCK_DATA_SIZE) {
+        blocked_payload.extend_from_slice(chunk);
+        blocked_payload.resize(blocked_payload.len() + BLOCK_DATA_SIZE - chunk.len(), padding);
+        blocked_payload.extend_from_slice(&[padding, padding]);
+    }
+
+    blocked_payload
+}
+
+fn remove_blocking_chunks(payload: Vec<u8>) -> Vec<u8> {
     // removing @@ signs (1024 blockings)
     let mut deblocked_payload: Vec<u8> = vec![];
     let trailing_block_size = payload
@@ -63,8 +110,8 @@ fn remove_blocking_chunks<'a>(payload: Vec<u8>) -> Vec<u8> {
     let payload_in_chunks = payload.chunks(2).enumerate();
 
     for (pos, two_bytes) in payload_in_chunks {
-        let is_not_a_zero_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == &[0u8, 0u8]);
-        let is_not_a_40_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == &[b'@', b'@']);
+        let is_not_a_zero_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == [0u8, 0u8]);
+        let is_not_a_40_block = !(pos > 0 && (pos % 507 == 506) && two_bytes == b"@@");
         let is_not_a_trailing_block = trailing_block_position >= pos * 2;
 
         if is_not_a_40_block && is_not_a_trailing_block && is_not_a_zero_block {
@@ -75,18 +122,27 @@ fn remove_blocking_chunks<'a>(payload: Vec<u8>) -> Vec<u8> {
     deblocked_payload
 }
 
-fn has_rdw_or_block(payload: &[u8]) -> bool {
-    let payload_vec = payload.to_vec();
+pub(crate) fn has_rdw_or_block(payload: &[u8]) -> bool {
     // checks if there is a non ascii character (rdw isn't ascii)
-    let rdw_probability = payload_vec.iter().take(4).filter(|c| c.is_ascii()).count();
+    let rdw_probability = payload.iter().take(4).filter(|c| c.is_ascii()).count();
 
     // if no last byte exists the file does not exist
-    let last_byte = payload_vec.last().unwrap_or(&b'0');
+    let last_byte = payload.last().unwrap_or(&b'0');
 
     //when there are non-ascii chars as in rdw and it ends with a block, it is high the probability of having rdw and @@
     rdw_probability >= 3 && (last_byte == &b'@' || last_byte == &0u8)
 }
 
+/// Whether the payload follows the 1014 blocking, each block ending with `@@` (or two zeros),
+/// rather than being RDW records whose length happens to be a multiple of 1014
+pub(crate) fn is_blocked(payload: &[u8]) -> bool {
+    !payload.is_empty()
+        && payload.len() % BLOCK_SIZE == 0
+        && payload
+            .chunks(BLOCK_SIZE)
+            .all(|block| matches!(&block[BLOCK_DATA_SIZE..], b"@@" | [0, 0]))
+}
+
 /// Each subsequent byte has a potential value of 255 (because it's in ASCII)
 /// so a RDW of 0u8 0u8 1u8 3u8 actually means that the RDW refers to the next 258 characters
 /// (0 × 255³) + (0 × 255²) + (1 × 255¹) + (3 × 255⁰) = 258
@@ -102,7 +158,7 @@ fn rdw_to_size(raw_rdw_buffer: &[u8], position: usize) -> Option<usize> {
         .map(|(index, rdw_number)| -> u64 {
             let index_translation = i8::abs(i8::try_from(index).unwrap() - 3i8);
             let index_power: u64 = 256u64.pow(u32::try_from(index_translation).unwrap());
-            u64::try_from(*rdw_number).unwrap() * index_power
+            u64::from(*rdw_number) * index_power
         })
         .sum();
 
@@ -116,7 +172,25 @@ fn rdw_to_size(raw_rdw_buffer: &[u8], position: usize) -> Option<usize> {
 
 #[test]
 fn test_opening_blocked_file() {
-    let file = read_file("tests/R111_sample.ipm");
+    let file = read_file("tests/R111_sample.ipm").unwrap();
 
     deblock_and_remove_rdw_from(file).unwrap();
 }
+
+#[test]
+fn test_blocking_and_deblocking() {
+    let records = vec![b"1644first".to_vec(), vec![b'9'; 1500]];
+
+    let blocked = block(add_rdw_to(&records));
+    assert_eq!(blocked.len(), 2 * BLOCK_SIZE);
+    assert_eq!(&blocked[BLOCK_DATA_SIZE..BLOCK_SIZE], b"@@");
+
+    let (deblocked, boundaries) = deblock_into_records(blocked).unwrap();
+    assert_eq!(deblocked, records.concat());
+    assert_eq!(boundaries, Some(vec![0..9, 9..1509]));
+}
+
+#[test]
+fn test_reading_a_missing_file() {
+    assert!(read_file("tests/missing.ipm").is_err());
+}
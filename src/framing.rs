@@ -0,0 +1,378 @@
+use crate::iso_specs::IsoSpecs;
+use crate::{parse_message, writer, Message};
+use eyre::{eyre, Result};
+use std::io::{ErrorKind, Read, Write};
+
+const TPDU_SIZE: usize = 5;
+
+/// Length header preceding each online message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthHeader {
+    /// 2 bytes, big endian, e.g `[0x00, 0x2A]` for 42 bytes
+    Binary2,
+    /// 4 ASCII digits, e.g `"0042"` for 42 bytes
+    Ascii4,
+}
+
+impl LengthHeader {
+    pub fn size(&self) -> usize {
+        match *self {
+            LengthHeader::Binary2 => 2,
+            LengthHeader::Ascii4 => 4,
+        }
+    }
+
+    pub fn encode(&self, length: usize) -> Result<Vec<u8>> {
+        match *self {
+            LengthHeader::Binary2 => {
+                let length = u16::try_from(length).map_err(|_| eyre!("frame of {} bytes exceeds 2 bytes header", length))?;
+                Ok(length.to_be_bytes().to_vec())
+            }
+            LengthHeader::Ascii4 if length <= 9999 => Ok(format!("{:04}", length).into_bytes()),
+            LengthHeader::Ascii4 => Err(eyre!("frame of {} bytes exceeds 4 digits header", length)),
+        }
+    }
+
+    pub fn decode(&self, header: &[u8]) -> Result<usize> {
+        match *self {
+            LengthHeader::Binary2 => Ok(usize::from(u16::from_be_bytes([header[0], header[1]]))),
+            LengthHeader::Ascii4 => Ok(std::str::from_utf8(&header[0..4])?.parse::<usize>()?),
+        }
+    }
+}
+
+/// Transport Protocol Data Unit, used by some hosts between the length header and the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tpdu {
+    pub id: u8,
+    pub destination: [u8; 2],
+    pub source: [u8; 2],
+}
+
+impl Tpdu {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Tpdu> {
+        if bytes.len() < TPDU_SIZE {
+            return Err(eyre!("tpdu should have {} bytes: {:02X?}", TPDU_SIZE, bytes));
+        }
+
+        Ok(Tpdu {
+            id: bytes[0],
+            destination: [bytes[1], bytes[2]],
+            source: [bytes[3], bytes[4]],
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; TPDU_SIZE] {
+        [self.id, self.destination[0], self.destination[1], self.source[0], self.source[1]]
+    }
+
+    /// The TPDU of a response, with the addresses swapped
+    pub fn response(&self) -> Tpdu {
+        Tpdu {
+            id: self.id,
+            destination: self.source,
+            source: self.destination,
+        }
+    }
+}
+
+/// How messages are framed on the wire: a length header, optionally followed by a TPDU
+///
+/// The length on the header covers the TPDU and the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    pub header: LengthHeader,
+    pub tpdu: bool,
+}
+
+/// A framed message, still encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub tpdu: Option<Tpdu>,
+    pub payload: Vec<u8>,
+}
+
+impl Framing {
+    pub fn new(header: LengthHeader) -> Framing {
+        Framing { header, tpdu: false }
+    }
+
+    pub fn with_tpdu(mut self) -> Framing {
+        self.tpdu = true;
+        self
+    }
+
+    pub fn encode(&self, frame: &Frame) -> Result<Vec<u8>> {
+        let tpdu = match (self.tpdu, frame.tpdu) {
+            (true, Some(tpdu)) => tpdu.to_bytes().to_vec(),
+            (true, None) => return Err(eyre!("framing expects a tpdu")),
+            (false, Some(_)) => return Err(eyre!("framing has no tpdu")),
+            (false, None) => vec![],
+        };
+
+        let mut encoded = self.header.encode(tpdu.len() + frame.payload.len())?;
+        encoded.extend_from_slice(&tpdu);
+        encoded.extend_from_slice(&frame.payload);
+        Ok(encoded)
+    }
+
+    /// Decodes the frame at the start of the buffer, returning it along with the consumed bytes,
+    /// or `None` when the buffer doesn't hold a whole frame yet
+    pub fn decode(&self, buffer: &[u8]) -> Result<Option<(Frame, usize)>> {
+        let header_size = self.header.size();
+        if buffer.len() < header_size {
+            return Ok(None);
+        }
+
+        let length = self.header.decode(&buffer[0..header_size])?;
+        if buffer.len() < header_size + length {
+            return Ok(None);
+        }
+
+        let frame = self.frame_from(&buffer[header_size..header_size + length])?;
+        Ok(Some((frame, header_size + length)))
+    }
+
+    fn frame_from(&self, content: &[u8]) -> Result<Frame> {
+        if self.tpdu {
+            Ok(Frame {
+                tpdu: Some(Tpdu::from_bytes(content)?),
+                payload: content[TPDU_SIZE..].to_vec(),
+            })
+        } else {
+            Ok(Frame {
+                tpdu: None,
+                payload: content.to_vec(),
+            })
+        }
+    }
+}
+
+/// Reads and writes framed messages over any `Read + Write`, e.g a `TcpStream`
+///
+/// As an iterator it yields the parsed messages until the peer closes the connection
+pub struct MessageStream<'a, S: Read + Write> {
+    stream: S,
+    framing: Framing,
+    specs: &'a IsoSpecs,
+    /// TPDU of the last frame read, used when writing without an explicit one
+    pub last_tpdu: Option<Tpdu>,
+}
+
+impl<'a, S: Read + Write> MessageStream<'a, S> {
+    pub fn new(stream: S, framing: Framing, specs: &'a IsoSpecs) -> MessageStream<'a, S> {
+        MessageStream {
+            stream,
+            framing,
+            specs,
+            last_tpdu: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Reads the next frame, returning `None` when the stream ends before a new frame starts
+    pub fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let mut header = vec![0u8; self.framing.header.size()];
+
+        let mut read = 0;
+        while read < header.len() {
+            match self.stream.read(&mut header[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(eyre!("stream ended in the middle of a length header")),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut content = vec![0u8; self.framing.header.decode(&header)?];
+        self.stream.read_exact(&mut content)?;
+
+        let frame = self.framing.frame_from(&content)?;
+        self.last_tpdu = frame.tpdu;
+        Ok(Some(frame))
+    }
+
+    pub fn read_message(&mut self) -> Result<Option<Message>> {
+        match self.read_frame()? {
+            Some(frame) => Ok(Some(parse_message(&frame.payload, self.specs)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        self.stream.write_all(&self.framing.encode(frame)?)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Writes a message, replying to the last TPDU read (with its addresses swapped) when the
+    /// framing has a TPDU
+    pub fn write_message(&mut self, message: &Message) -> Result<()> {
+        let tpdu = match (self.framing.tpdu, self.last_tpdu) {
+            (true, Some(tpdu)) => Some(tpdu.response()),
+            _ => None,
+        };
+
+        self.write_message_with_tpdu(message, tpdu)
+    }
+
+    pub fn write_message_with_tpdu(&mut self, message: &Message, tpdu: Option<Tpdu>) -> Result<()> {
+        let payload = writer::encode_message(self.specs, message)?;
+        self.write_frame(&Frame { tpdu, payload })
+    }
+}
+
+impl<'a, S: Read + Write> Iterator for MessageStream<'a, S> {
+    type Item = Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+/// Tokio codec for framed messages, e.g `Framed::new(tcp_stream, MessageCodec::new(framing, specs))`
+#[cfg(feature = "tokio")]
+pub mod codec {
+    use super::{Frame, Framing};
+    use crate::iso_specs::IsoSpecs;
+    use crate::{parse_message, writer, Message};
+    use bytes::{Buf, BytesMut};
+    use std::sync::Arc;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    pub struct FrameCodec {
+        framing: Framing,
+    }
+
+    impl FrameCodec {
+        pub fn new(framing: Framing) -> FrameCodec {
+            FrameCodec { framing }
+        }
+    }
+
+    impl Decoder for FrameCodec {
+        type Item = Frame;
+        type Error = eyre::Report;
+
+        fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Self::Error> {
+            match self.framing.decode(src)? {
+                Some((frame, consumed)) => {
+                    src.advance(consumed);
+                    Ok(Some(frame))
+                }
+                None => Ok(None),
+            }
+        }
+    }
+
+    impl Encoder<Frame> for FrameCodec {
+        type Error = eyre::Report;
+
+        fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+            dst.extend_from_slice(&self.framing.encode(&frame)?);
+            Ok(())
+        }
+    }
+
+    /// Decodes frames into messages, dropping their TPDU; use `FrameCodec` when the TPDU is needed
+    pub struct MessageCodec {
+        frames: FrameCodec,
+        specs: Arc<IsoSpecs>,
+    }
+
+    impl MessageCodec {
+        pub fn new(framing: Framing, specs: Arc<IsoSpecs>) -> MessageCodec {
+            MessageCodec {
+                frames: FrameCodec::new(framing),
+                specs,
+            }
+        }
+    }
+
+    impl Decoder for MessageCodec {
+        type Item = Message;
+        type Error = eyre::Report;
+
+        fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, Self::Error> {
+            match self.frames.decode(src)? {
+                Some(frame) => Ok(Some(parse_message(&frame.payload, &self.specs)?)),
+                None => Ok(None),
+            }
+        }
+    }
+
+    impl Encoder<&Message> for MessageCodec {
+        type Error = eyre::Report;
+
+        fn encode(&mut self, message: &Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+            let payload = writer::encode_message(&self.specs, message)?;
+            self.frames.encode(Frame { tpdu: None, payload }, dst)
+        }
+    }
+
+    #[test]
+    fn test_message_codec_waits_for_whole_frames() {
+        use crate::builder::MessageBuilder;
+        use crate::framing::LengthHeader;
+
+        let specs = Arc::new(IsoSpecs::iso8583_1987());
+        let echo = MessageBuilder::new(&specs)
+            .mti("0800")
+            .de(7, "1018120000")
+            .de(11, "1")
+            .de(70, "301")
+            .build()
+            .unwrap();
+
+        let mut codec = MessageCodec::new(Framing::new(LengthHeader::Binary2), specs.clone());
+        let mut encoded = BytesMut::new();
+        codec.encode(&echo, &mut encoded).unwrap();
+        codec.encode(&echo, &mut encoded).unwrap();
+
+        let mut buffer = BytesMut::from(&encoded[0..10]);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(&encoded[10..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap().unwrap().data_elements["070"].get_string(), "301");
+        assert_eq!(codec.decode(&mut buffer).unwrap().unwrap().mti, "0800");
+        assert!(buffer.is_empty());
+    }
+}
+
+#[test]
+fn test_framing_headers_and_tpdu() {
+    let tpdu = Tpdu {
+        id: 0x60,
+        destination: [0x00, 0x01],
+        source: [0x00, 0x02],
+    };
+    let frame = Frame {
+        tpdu: Some(tpdu),
+        payload: b"0800".to_vec(),
+    };
+
+    let framing = Framing::new(LengthHeader::Binary2).with_tpdu();
+    let encoded = framing.encode(&frame).unwrap();
+    assert_eq!(encoded, vec![0x00, 0x09, 0x60, 0x00, 0x01, 0x00, 0x02, b'0', b'8', b'0', b'0']);
+    assert_eq!(framing.decode(&encoded[0..8]).unwrap(), None);
+    assert_eq!(framing.decode(&encoded).unwrap(), Some((frame.clone(), 11)));
+    assert_eq!(tpdu.response().destination, [0x00, 0x02]);
+
+    let framing = Framing::new(LengthHeader::Ascii4);
+    assert!(framing.encode(&frame).is_err());
+    let frame = Frame {
+        tpdu: None,
+        payload: b"0800".to_vec(),
+    };
+    assert_eq!(framing.encode(&frame).unwrap(), b"00040800");
+    assert!(LengthHeader::Ascii4.encode(10_000).is_err());
+    assert!(LengthHeader::Binary2.encode(65_536).is_err());
+}
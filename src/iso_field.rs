@@ -10,7 +10,7 @@ use super::*;
 use serde::Serializer;
 use strum_macros;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, strum_macros::EnumProperty)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, strum_macros::EnumProperty)]
 pub enum FieldCharType {
     #[strum(props(content_type = "number"))]
     Iso8583_n,
@@ -36,15 +36,13 @@ pub enum FieldCharType {
     Iso8583_bmps,
     #[strum(props(content_type = "string"))]
     Iso8583_mti,
+    #[default]
     #[strum(props(content_type = "binary"))]
     Iso8583_undefined,
 }
 
-impl Default for FieldCharType {
-    fn default() -> Self { FieldCharType::Iso8583_undefined }
-}
-
 impl FieldCharType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<FieldCharType> {
         match s {
             "n" => Some(FieldCharType::Iso8583_n),
@@ -64,26 +62,51 @@ impl FieldCharType {
         }
     }
 
+    /// Checks if the textual content only holds characters allowed by the char type
+    ///
+    /// Alphabetic types accept spaces, since those are used as padding on fixed fields.
+    /// Binary types accept anything.
+    pub fn accepts(&self, value: &str) -> bool {
+        let is_special = |c: char| c.is_ascii_graphic() && !c.is_ascii_alphanumeric() || c == ' ';
+
+        match *self {
+            FieldCharType::Iso8583_n => value.chars().all(|c| c.is_ascii_digit()),
+            FieldCharType::Iso8583_ns => value.chars().all(|c| c.is_ascii_digit() || is_special(c)),
+            FieldCharType::Iso8583_xn => {
+                let mut chars = value.chars();
+                matches!(chars.next(), Some('C') | Some('D')) && chars.all(|c| c.is_ascii_digit())
+            }
+            FieldCharType::Iso8583_a => value.chars().all(|c| c.is_ascii_alphabetic() || c == ' '),
+            FieldCharType::Iso8583_an | FieldCharType::Iso8583_anp => {
+                value.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ')
+            }
+            FieldCharType::Iso8583_ans | FieldCharType::Iso8583_mti => {
+                value.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+            }
+            _ => true,
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
-        match self {
-            &FieldCharType::Iso8583_n => "n",
-            &FieldCharType::Iso8583_ns => "ns",
-            &FieldCharType::Iso8583_xn => "xs",
-            &FieldCharType::Iso8583_a => "a",
-            &FieldCharType::Iso8583_an => "an",
-            &FieldCharType::Iso8583_ans => "ans",
-            &FieldCharType::Iso8583_ansb => "ansb",
-            &FieldCharType::Iso8583_anp => "anp",
-            &FieldCharType::Iso8583_b => "b",
-            &FieldCharType::Iso8583_z => "z",
-            &FieldCharType::Iso8583_bmps => "bmps",
-            &FieldCharType::Iso8583_mti => "mti",
-            &FieldCharType::Iso8583_undefined => "undefined",
+        match *self {
+            FieldCharType::Iso8583_n => "n",
+            FieldCharType::Iso8583_ns => "ns",
+            FieldCharType::Iso8583_xn => "xs",
+            FieldCharType::Iso8583_a => "a",
+            FieldCharType::Iso8583_an => "an",
+            FieldCharType::Iso8583_ans => "ans",
+            FieldCharType::Iso8583_ansb => "ansb",
+            FieldCharType::Iso8583_anp => "anp",
+            FieldCharType::Iso8583_b => "b",
+            FieldCharType::Iso8583_z => "z",
+            FieldCharType::Iso8583_bmps => "bmps",
+            FieldCharType::Iso8583_mti => "mti",
+            FieldCharType::Iso8583_undefined => "undefined",
         }
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FieldSizeType {
     Fixed,
     LlVar,
@@ -93,6 +116,7 @@ pub enum FieldSizeType {
 }
 
 impl FieldSizeType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<FieldSizeType> {
         let s_lower = s.to_lowercase();
         match s_lower.as_str() {
@@ -106,24 +130,74 @@ impl FieldSizeType {
     }
 
     pub fn as_str(&self) -> &'static str {
-        match self {
-            &FieldSizeType::Fixed => "fixed",
-            &FieldSizeType::LlVar => "llvar",
-            &FieldSizeType::LllVar => "lllvar",
-            &FieldSizeType::LlllVar => "llllvar",
-            &FieldSizeType::BitMap => "bitmap",
+        match *self {
+            FieldSizeType::Fixed => "fixed",
+            FieldSizeType::LlVar => "llvar",
+            FieldSizeType::LllVar => "lllvar",
+            FieldSizeType::LlllVar => "llllvar",
+            FieldSizeType::BitMap => "bitmap",
+        }
+    }
+}
+
+/// How the bytes of a length prefix or of a field content are represented on the wire
+///
+/// BCD packs two digits per byte, padding odd lengths with a zero nibble on the left or an `F`
+/// nibble on the right. Binary length prefixes are big endian, using 1 byte for LLVar and 2 bytes
+/// for LLLVar and LLLLVar; binary contents are kept as they are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldEncoding {
+    #[default]
+    Ascii,
+    Ebcdic,
+    BcdLeftPadded,
+    BcdRightPadded,
+    Binary,
+}
+
+impl FieldEncoding {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<FieldEncoding> {
+        let s_lower = s.to_lowercase();
+        match s_lower.as_str() {
+            "ascii" => Some(FieldEncoding::Ascii),
+            "ebcdic" => Some(FieldEncoding::Ebcdic),
+            "bcd-left-padded" => Some(FieldEncoding::BcdLeftPadded),
+            "bcd-right-padded" => Some(FieldEncoding::BcdRightPadded),
+            "binary" => Some(FieldEncoding::Binary),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FieldEncoding::Ascii => "ascii",
+            FieldEncoding::Ebcdic => "ebcdic",
+            FieldEncoding::BcdLeftPadded => "bcd-left-padded",
+            FieldEncoding::BcdRightPadded => "bcd-right-padded",
+            FieldEncoding::Binary => "binary",
         }
     }
+
+    /// Whether the encoded content can be read as is, without converting it to ASCII
+    pub fn is_ascii_compatible(&self) -> bool {
+        matches!(self, FieldEncoding::Ascii | FieldEncoding::Binary)
+    }
 }
 
 /// `IsoField` defination
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IsoField {
     pub label: String,
     pub label_id: String,
     pub char_type: FieldCharType,
     pub size_type: FieldSizeType,
+    /// max length of the content, in characters (digits for BCD, bytes for binary)
     pub length: usize,
+    #[serde(default)]
+    pub length_encoding: FieldEncoding,
+    #[serde(default)]
+    pub content_encoding: FieldEncoding,
 }
 
 /// `IsoField` implementation
@@ -141,11 +215,40 @@ impl IsoField {
             char_type,
             length,
             size_type,
+            length_encoding: FieldEncoding::Ascii,
+            content_encoding: FieldEncoding::Ascii,
         }
     }
+
+    /// Sets how the length prefix of a variable field is encoded, e.g `FieldEncoding::Binary`
+    pub fn with_length_encoding(mut self, length_encoding: FieldEncoding) -> IsoField {
+        self.length_encoding = length_encoding;
+        self
+    }
+
+    /// Sets how the field content is encoded, e.g `FieldEncoding::BcdLeftPadded` for packed numbers
+    pub fn with_content_encoding(mut self, content_encoding: FieldEncoding) -> IsoField {
+        self.content_encoding = content_encoding;
+        self
+    }
+
+    /// Number of digits of the length prefix, 0 for fixed fields
+    pub fn length_prefix_digits(&self) -> usize {
+        match self.size_type {
+            FieldSizeType::LlVar => 2,
+            FieldSizeType::LllVar => 3,
+            FieldSizeType::LlllVar => 4,
+            FieldSizeType::Fixed | FieldSizeType::BitMap => 0,
+        }
+    }
+
+    /// Number of bytes of the length prefix, 0 for fixed fields
+    pub fn length_prefix_size(&self) -> usize {
+        encoding::prefix_size(self.length_encoding, self.length_prefix_digits())
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum IPMValue {
     u64(u64),
     String(String),
@@ -153,6 +256,22 @@ pub enum IPMValue {
 }
 
 impl IPMValue {
+    /// Builds the value from an ASCII content, according to the content type of the field
+    ///
+    /// Variable length numbers (e.g DE 33 or DE 93) are kept as strings, their leading zeros being
+    /// part of the value and of its length prefix. Strings that aren't UTF-8, e.g a message embedded
+    /// in DE 72, are kept as binary so they are written back as they were read
+    pub fn from_content(char_type: &FieldCharType, variable_length: bool, bytes: Vec<u8>) -> eyre::Result<IPMValue> {
+        match char_type.get_str("content_type") {
+            Some("string") => Ok(String::from_utf8(bytes)
+                .map(IPMValue::String)
+                .unwrap_or_else(|error| IPMValue::Binary(error.into_bytes()))),
+            Some("number") if variable_length => Ok(IPMValue::String(String::from_utf8_lossy(&bytes).to_string())),
+            Some("number") => Ok(IPMValue::u64(String::from_utf8_lossy(&bytes).parse::<u64>()?)),
+            _ => Ok(IPMValue::Binary(bytes)),
+        }
+    }
+
     pub fn get_string(&self) -> String {
         match self {
             IPMValue::u64(num) => format!("{num}"),
@@ -185,28 +304,21 @@ pub struct FieldPayload {
     pub index: usize,
     pub len: usize,
     pub tag_len: usize, // the length of the tag e.g LLLVar = 3
+    pub content_encoding: FieldEncoding,
+    pub content_length: usize, // in characters, e.g the number of digits for BCD
 }
 
 impl FieldPayload {
-    pub fn iso_field_value<'a>(&self, buffer: &'a [u8]) -> Vec<u8> {
+    pub fn iso_field_value(&self, buffer: &[u8]) -> Vec<u8> {
         buffer[self.index + self.tag_len..self.index + self.len].to_vec()
     }
 
-    pub fn get_ipm_value(&self, buffer: &[u8]) -> eyre::Result<IPMValue> {
-        let bytes = self.iso_field_value(buffer);
-
-        if self.char_type.get_str("content_type") == Some("string") {
-            let utf8_string = String::from_utf8_lossy(&bytes).to_string();
-
-            Ok(IPMValue::String(utf8_string))
-        } else if self.char_type.get_str("content_type") == Some("number") {
-            let utf8_string = String::from_utf8_lossy(&bytes).to_string();
-
-            let num = utf8_string.parse::<u64>()?;
+    /// Field content converted back to ASCII from its encoding, e.g unpacking BCD digits
+    pub fn decoded_value(&self, buffer: &[u8]) -> eyre::Result<Vec<u8>> {
+        encoding::decode_content(self.content_encoding, &self.iso_field_value(buffer), self.content_length)
+    }
 
-            Ok(IPMValue::u64(num))
-        } else {
-            Ok(IPMValue::Binary(bytes))
-        }
+    pub fn get_ipm_value(&self, buffer: &[u8]) -> eyre::Result<IPMValue> {
+        IPMValue::from_content(&self.char_type, self.tag_len > 0, self.decoded_value(buffer)?)
     }
 }
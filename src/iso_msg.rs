@@ -6,17 +6,184 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::encoding;
 use crate::iso_field::FieldCharType;
 use crate::iso_field::FieldPayload;
 use crate::iso_field::FieldSizeType;
 use crate::iso_field::IsoField;
 use crate::iso_specs::IsoSpecs;
+use crate::message_ref::MessageRef;
 use bit_array::BitArray;
+use eyre::eyre;
 use std::borrow::Cow;
 use std::fmt;
 use std::ops::Deref;
 use std::str;
-use typenum::U128;
+use typenum::{U128, U192};
+
+/// Up to three bitmaps (primary, secondary and tertiary), covering DEs 1 to 192
+pub type Bitmap = BitArray<u64, U192>;
+
+const BITMAP_SIZE: usize = 8;
+
+/// How each bitmap is represented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitmapFormat {
+    /// 8 bytes, as on IPM files
+    Binary,
+    /// 16 ASCII hex characters
+    Hex,
+}
+
+impl BitmapFormat {
+    /// Encoded size of a single bitmap
+    pub fn size(&self) -> usize {
+        match self {
+            BitmapFormat::Binary => BITMAP_SIZE,
+            BitmapFormat::Hex => BITMAP_SIZE * 2,
+        }
+    }
+
+    pub fn encode(&self, bitmap_bytes: &[u8]) -> Vec<u8> {
+        match self {
+            BitmapFormat::Binary => bitmap_bytes.to_vec(),
+            BitmapFormat::Hex => bitmap_bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<String>()
+                .into_bytes(),
+        }
+    }
+
+    /// Decodes a single bitmap from the beginning of `encoded` into its 8 bytes
+    pub fn decode(&self, encoded: &[u8]) -> Result<Vec<u8>, String> {
+        let bitmap = encoded
+            .get(0..self.size())
+            .ok_or_else(|| format!("bitmap should have {} bytes", self.size()))?;
+
+        match self {
+            BitmapFormat::Binary => Ok(bitmap.to_vec()),
+            BitmapFormat::Hex => bitmap
+                .chunks(2)
+                .map(|hex_byte| {
+                    str::from_utf8(hex_byte)
+                        .ok()
+                        .and_then(|hex_byte| u8::from_str_radix(hex_byte, 16).ok())
+                        .ok_or_else(|| format!("invalid hex bitmap {:?}", String::from_utf8_lossy(bitmap)))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// When the secondary bitmap is present
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondaryBitmap {
+    /// Always present, e.g IPM files
+    Always,
+    /// Present only when bit 1 is set
+    WhenFlagged,
+}
+
+/// Bitmaps layout of a message, part of the `IsoSpecs`
+///
+/// When `tertiary` is enabled, bit 65 flags the presence of the tertiary bitmap (DEs 129 to 192),
+/// so DE 65 is part of the bitmaps instead of being a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitmapLayout {
+    pub format: BitmapFormat,
+    pub secondary: SecondaryBitmap,
+    pub tertiary: bool,
+}
+
+impl Default for BitmapLayout {
+    fn default() -> Self {
+        BitmapLayout {
+            format: BitmapFormat::Binary,
+            secondary: SecondaryBitmap::Always,
+            tertiary: false,
+        }
+    }
+}
+
+impl BitmapLayout {
+    /// Builds the bitmaps for a set of DE numbers, flagging the secondary and tertiary bitmaps when needed
+    pub fn bitmap_for<I>(&self, field_numbers: I) -> Bitmap
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut bitmap = Bitmap::from_elem(false);
+
+        for field_number in field_numbers {
+            if (2..=192).contains(&field_number) {
+                bitmap.set(field_number - 1, true);
+            }
+        }
+
+        if self.tertiary && (128..192).any(|bit| bitmap.get(bit) == Some(true)) {
+            bitmap.set(64, true);
+        }
+
+        if self.secondary == SecondaryBitmap::Always || (64..192).any(|bit| bitmap.get(bit) == Some(true)) {
+            bitmap.set(0, true);
+        }
+
+        bitmap
+    }
+
+    fn has_secondary(&self, bitmap: &Bitmap) -> bool {
+        self.secondary == SecondaryBitmap::Always || bitmap.get(0) == Some(true)
+    }
+
+    fn has_tertiary(&self, bitmap: &Bitmap) -> bool {
+        self.tertiary && self.has_secondary(bitmap) && bitmap.get(64) == Some(true)
+    }
+
+    /// Splits the bitmaps into the primary, secondary and tertiary ones, when those are present
+    pub fn split(&self, bitmap: &Bitmap) -> ([u8; 8], Option<[u8; 8]>, Option<[u8; 8]>) {
+        let bytes = bitmap.to_bytes();
+        let mut bitmaps = bytes.chunks(BITMAP_SIZE).map(|bitmap_bytes| {
+            let mut bitmap_array = [0u8; BITMAP_SIZE];
+            bitmap_array.copy_from_slice(bitmap_bytes);
+            bitmap_array
+        });
+
+        let primary = bitmaps.next().unwrap_or_default();
+        let secondary = bitmaps.next().filter(|_| self.has_secondary(bitmap));
+        let tertiary = bitmaps.next().filter(|_| self.has_tertiary(bitmap));
+
+        (primary, secondary, tertiary)
+    }
+
+    pub fn encode(&self, bitmap: &Bitmap) -> Vec<u8> {
+        let bytes = bitmap.to_bytes();
+        let bitmaps_count = 1 + usize::from(self.has_secondary(bitmap)) + usize::from(self.has_tertiary(bitmap));
+
+        bytes
+            .chunks(BITMAP_SIZE)
+            .take(bitmaps_count)
+            .flat_map(|bitmap_bytes| self.format.encode(bitmap_bytes))
+            .collect()
+    }
+
+    /// Decodes the bitmaps from the beginning of `encoded`, returning them along with their encoded size
+    pub fn decode(&self, encoded: &[u8]) -> Result<(Bitmap, usize), String> {
+        let mut bytes = self.format.decode(encoded)?;
+        let mut bitmap = Bitmap::from_bytes(&bytes);
+
+        if self.has_secondary(&bitmap) {
+            bytes.append(&mut self.format.decode(&encoded[bytes.len() / BITMAP_SIZE * self.format.size()..])?);
+            bitmap = Bitmap::from_bytes(&bytes);
+        }
+
+        if self.has_tertiary(&bitmap) {
+            bytes.append(&mut self.format.decode(&encoded[bytes.len() / BITMAP_SIZE * self.format.size()..])?);
+            bitmap = Bitmap::from_bytes(&bytes);
+        }
+
+        Ok((bitmap, bytes.len() / BITMAP_SIZE * self.format.size()))
+    }
+}
 
 /// `IsoMsg`
 pub struct IsoMsg<'a, 'b> {
@@ -43,14 +210,16 @@ impl fmt::Debug for IsoMsg<'_, '_> {
 }
 
 impl<'a, 'b> IsoMsg<'a, 'b> {
-    pub fn new(iso_spec: &'b IsoSpecs, payload: &'a [u8]) -> IsoMsg<'a, 'b> {
-        let fields = IsoMsg::from_byte_array(iso_spec, payload);
+    /// Parses a message, failing when its bitmaps or length prefixes can't be decoded or
+    /// when a field goes past the end of the payload
+    pub fn new(iso_spec: &'b IsoSpecs, payload: &'a [u8]) -> eyre::Result<IsoMsg<'a, 'b>> {
+        let fields = IsoMsg::from_byte_array(iso_spec, payload)?;
 
-        IsoMsg {
+        Ok(IsoMsg {
             iso_spec,
             payload: Cow::Borrowed(payload),
             fields,
-        }
+        })
     }
 
     pub fn length(&self) -> usize {
@@ -58,52 +227,76 @@ impl<'a, 'b> IsoMsg<'a, 'b> {
     }
 
     pub fn remove_field(&mut self, index: usize) -> Result<(), &str> {
-        assert!(index < self.fields.len());
-        assert!(index < self.iso_spec.specs.len());
+        if index >= self.fields.len() || index >= self.iso_spec.specs.len() {
+            return Err("Field index out of range");
+        }
         self.fields[index].exist = false;
         Ok(())
     }
 
+    /// Sets the content of a field (without its length prefix), which is appended to the message payload
     pub fn set_field(&mut self, index: usize, buffer: &[u8]) -> Result<(), &str> {
         trace!(
             "set_field: index:{}, buffer:{}",
             index,
-            str::from_utf8(buffer).unwrap()
+            String::from_utf8_lossy(buffer)
         );
-        assert!(index < self.fields.len());
-        assert!(index < self.iso_spec.specs.len());
-        assert!(buffer.len() <= self.iso_spec.specs[index].length);
+        if index >= self.fields.len() || index >= self.iso_spec.specs.len() {
+            return Err("Field index out of range");
+        }
+
+        let iso_field = &self.iso_spec.specs[index];
+        if buffer.len() > iso_field.length {
+            return Err("Field value longer than its spec");
+        }
+        if iso_field.size_type == FieldSizeType::Fixed && buffer.len() != iso_field.length {
+            return Err("Fixed field length mismatch");
+        }
+
+        let content = match encoding::encode_content(iso_field.content_encoding, buffer) {
+            Ok(content) => content,
+            Err(_) => return Err("Field content doesn't fit its encoding"),
+        };
 
         let len_prefix = self.get_field_length_prefix(index);
-        let total_lenth = buffer.len() + len_prefix;
+        let total_lenth = content.len() + len_prefix;
         let mut v = Vec::with_capacity(total_lenth);
         trace!(
             "buffer.len():{}, iso_spec.specs[index].length:{}",
             buffer.len(),
-            self.iso_spec.specs[index].length
+            iso_field.length
         );
         if len_prefix > 0 {
-            v.extend_from_slice(format!("{:0w$}", buffer.len(), w = len_prefix).as_bytes());
+            match encoding::encode_length_prefix(iso_field.length_encoding, buffer.len(), iso_field.length_prefix_digits()) {
+                Ok(prefix) => v.extend_from_slice(&prefix),
+                Err(_) => return Err("Field length doesn't fit its prefix"),
+            }
         }
-        v.extend_from_slice(buffer);
+        v.extend_from_slice(&content);
 
-        trace!(
-            "index:{}, set_extend_from_slice : v {}",
-            index,
-            str::from_utf8(&v).unwrap()
-        );
         trace!("set_field: v.len:{}", v.len());
-        self.fields[index].exist = true;
+
+        let payload = self.payload.to_mut();
+        let field_index = payload.len();
+        payload.extend_from_slice(&v);
+
+        self.fields[index] = FieldPayload {
+            index: field_index,
+            len: v.len(),
+            tag_len: len_prefix,
+            exist: true,
+            iso_field_label: Some(iso_field.label.clone()),
+            iso_field_label_id: iso_field.label_id.clone(),
+            char_type: iso_field.char_type.clone(),
+            content_encoding: iso_field.content_encoding,
+            content_length: buffer.len(),
+        };
         Ok(())
     }
 
+    /// Size in bytes of the length prefix of a field
     pub fn get_field_length_prefix(&self, index: usize) -> usize {
-        match self.iso_spec.specs[index].size_type {
-            FieldSizeType::LlVar => 2,
-            FieldSizeType::LllVar => 3,
-            FieldSizeType::LlllVar => 4,
-            _ => 0,
-        }
+        self.iso_spec.specs[index].length_prefix_size()
     }
 
     pub fn present_fields(&self) -> Vec<&FieldPayload> {
@@ -178,136 +371,90 @@ impl<'a, 'b> IsoMsg<'a, 'b> {
             + (u32::from(array[3]) << 24)
     }
 
-    pub fn to_byte_array(&self, buffer: &mut [u8]) -> usize {
-        let mut buffer_index = 0usize;
-        let num_iteration: usize = (self.iso_spec.specs.len() - 1 + 63) / 128;
-        let mut bit_arrays = Vec::<BitArray<u64, U128>>::with_capacity(num_iteration);
-        for _ in 0..num_iteration {
-            bit_arrays.push(BitArray::<u64, U128>::from_elem(false));
-        }
-        let mut bit_array_index = 0;
-        let mut bit_index = 0;
-        let mut bitmap_field_index = 0;
-
-        let mut bitmap_found = false;
-
-        //XXX seems here that the lib is expecting to parse the mti; the bitmap; the fields itself lol
-        // TODO maybe implement a pointer solution, since we will get a lot of messages one after another
-        for index in 0..self.fields.len() {
-            bit_array_index = index / 128;
-
-            let is_a_bitmap = !bitmap_found
-                && (self.iso_spec.specs[index].char_type == FieldCharType::Iso8583_bmps);
-            if is_a_bitmap {
-                bitmap_field_index = index;
-                bitmap_found = true;
-                bit_index = buffer_index;
-                let res = self.get_field_raw(index, &mut buffer[buffer_index..]);
-                if res.is_ok() {
-                    let (field_total_len, _) = res.unwrap();
-                    buffer_index += field_total_len;
-                }
-            } else {
-                let res = self.get_field_raw(index, &mut buffer[buffer_index..]);
-                if res.is_ok() {
-                    if bitmap_found {
-                        bit_arrays[bit_array_index].set(index - bitmap_field_index, true);
-                        trace!(
-                            "index:{}, buffer[buffer_index..]:{}",
-                            index,
-                            str::from_utf8(&buffer[buffer_index..]).unwrap()
-                        );
-                    }
-                    let (field_total_len, _) = res.unwrap();
-                    buffer_index += field_total_len;
-                }
-            }
-        }
-        //override bitmap XXX but why??????
-        let mut bitmap = String::with_capacity(bit_array_index * 16);
-        for (i, bit_array_item) in bit_arrays.iter_mut().enumerate().take(bit_array_index) {
-            if i == 0 && bit_array_item.len() > 64 {
-                bit_array_item.set(0, true);
+    /// Bitmaps of the present fields, following the bitmap layout of the spec
+    pub fn bitmap(&self) -> Bitmap {
+        // i0 and i1 are mti and bitmap
+        let field_numbers = self
+            .fields
+            .iter()
+            .enumerate()
+            .skip(2)
+            .filter(|(_, field)| field.exist)
+            .map(|(index, _)| index);
+
+        self.iso_spec.bitmap_layout.bitmap_for(field_numbers)
+    }
+
+    /// Encodes the message as mti, bitmaps (computed from the present fields) and the fields with their length prefix
+    ///
+    /// Bitmaps follow the layout of the spec, apart from their format, which is given by `bitmap_format`
+    pub fn to_byte_array(&self, bitmap_format: BitmapFormat) -> Vec<u8> {
+        let bitmap_layout = BitmapLayout {
+            format: bitmap_format,
+            ..self.iso_spec.bitmap_layout
+        };
+        let mut buffer = Vec::with_capacity(self.length() + bitmap_format.size() * 2);
+
+        for (index, field) in self.fields.iter().enumerate() {
+            if !field.exist {
+                continue;
             }
-            let bytes = bit_array_item.to_bytes();
-            let mut byte_index = 0;
 
-            while byte_index < bytes.len() {
-                let ms_str = IsoMsg::convert_u32_be(&bytes[byte_index..byte_index + 4]);
-                byte_index += 4;
-                bitmap.push_str(&format!("{:08X}", ms_str));
+            if self.iso_spec.specs[index].char_type == FieldCharType::Iso8583_bmps {
+                buffer.append(&mut bitmap_layout.encode(&self.bitmap()));
+            } else {
+                buffer.extend_from_slice(&self.payload[field.index..field.index + field.len]);
             }
         }
-        buffer[bit_index..bitmap.len() + bit_index]
-            .copy_from_slice(&bitmap.as_bytes()[0..bitmap.len()]);
-        buffer_index
+
+        buffer
+    }
+
+    /// Returns the field size in bytes (including its length prefix) and the size of the length prefix
+    pub fn get_field_length(iso_field: &IsoField, input_buffer: &[u8]) -> eyre::Result<(usize, usize)> {
+        let (content_length, tag_len) = IsoMsg::get_content_length(iso_field, input_buffer)?;
+        Ok((tag_len + encoding::encoded_size(iso_field.content_encoding, content_length), tag_len))
     }
 
-    pub fn get_field_length(iso_field: &IsoField, input_buffer: &[u8]) -> (usize, usize) {
+    /// Returns the field content length in characters (as declared by its prefix or spec) and
+    /// the size of the length prefix
+    pub fn get_content_length(iso_field: &IsoField, input_buffer: &[u8]) -> eyre::Result<(usize, usize)> {
         match iso_field.size_type {
-            FieldSizeType::Fixed | FieldSizeType::BitMap => (iso_field.length, 0),
-            FieldSizeType::LlVar => {
-                let str_digits = str::from_utf8(&input_buffer[0..2]).unwrap();
-                (usize::from_str_radix(str_digits, 10).unwrap() + 2, 2)
-            }
-            FieldSizeType::LllVar => {
-                let str_digits = str::from_utf8(&input_buffer[0..3]).unwrap();
-                (usize::from_str_radix(str_digits, 10).unwrap() + 3, 3)
-            }
-            FieldSizeType::LlllVar => {
-                let str_digits = str::from_utf8(&input_buffer[0..4]).unwrap();
-                (usize::from_str_radix(str_digits, 10).unwrap() + 4, 4)
+            FieldSizeType::Fixed | FieldSizeType::BitMap => Ok((iso_field.length, 0)),
+            FieldSizeType::LlVar | FieldSizeType::LllVar | FieldSizeType::LlllVar => {
+                let tag_len = iso_field.length_prefix_size();
+                let prefix = input_buffer
+                    .get(0..tag_len)
+                    .ok_or_else(|| eyre!("missing length prefix of {}", iso_field.label))?;
+                let content_length =
+                    encoding::decode_length_prefix(iso_field.length_encoding, prefix, iso_field.length_prefix_digits())
+                        .map_err(|e| eyre!("invalid length prefix of {}: {}", iso_field.label, e))?;
+                Ok((content_length, tag_len))
             }
         }
     }
 
-    //return a Result, create a debug param?
-    pub fn from_byte_array(iso_spec: &IsoSpecs, input_buffer: &[u8]) -> Vec<FieldPayload> {
-        let mut payload_index = 0usize;
-
-        let bit_array = &IsoMsg::process_bitmap(&input_buffer[4..4 + 16]);
-
-        let mut fields = Vec::with_capacity(iso_spec.specs.len());
-
-        for iso_spec_index in 0..iso_spec.specs.len() {
-            let iso_field: &IsoField = &iso_spec.specs[iso_spec_index];
-            let is_a_mti_or_bitmap = iso_spec_index == 0 || iso_spec_index == 1;
-
-            // i0 and i1 are bitmap and mti
-            let field_exist = is_a_mti_or_bitmap || bit_array.get(iso_spec_index - 1).unwrap();
-
-            let field = if field_exist {
-                let (len, tag_len) =
-                    IsoMsg::get_field_length(iso_field, &input_buffer[payload_index..]);
-                FieldPayload {
-                    index: payload_index,
-                    len,
-                    tag_len,
-                    exist: true,
-                    iso_field_label: Some(iso_field.label.clone()), //TODO use the reference instead of cloning everytime
-                    iso_field_label_id: iso_field.label_id.clone(),
-                    char_type: iso_field.char_type.clone(),
-                }
-            } else {
-                FieldPayload::default()
+    /// Splits a message into its fields, following the bitmaps and the length prefixes as
+    /// `MessageRef::parse` does
+    pub fn from_byte_array(iso_spec: &IsoSpecs, input_buffer: &[u8]) -> eyre::Result<Vec<FieldPayload>> {
+        let message_ref = MessageRef::parse(input_buffer, iso_spec)?;
+        let mut fields: Vec<FieldPayload> = iso_spec.specs.iter().map(|_| FieldPayload::default()).collect();
+
+        for field_ref in message_ref.fields.iter() {
+            let iso_field = field_ref.spec;
+            fields[field_ref.number] = FieldPayload {
+                index: field_ref.position,
+                len: field_ref.encoded_size(),
+                tag_len: iso_field.length_prefix_size(),
+                exist: true,
+                iso_field_label: Some(iso_field.label.clone()), //TODO use the reference instead of cloning everytime
+                iso_field_label_id: iso_field.label_id.clone(),
+                char_type: iso_field.char_type.clone(),
+                content_encoding: iso_field.content_encoding,
+                content_length: field_ref.content_length,
             };
-
-            if field.exist {
-                //TODO fill a buffer with array of errors
-                // dbg!((
-                //     IsoMsg::get_field_length(iso_field, &input_buffer[payload_index..]),
-                //     iso_field.label.clone(),
-                //     String::from_utf8_lossy(&input_buffer[0..4]),
-                //     String::from_utf8_lossy(
-                //         &input_buffer[payload_index..payload_index + field.len]
-                //     ),
-                //     format!("{:?}", bit_array)
-                // ));
-            }
-
-            payload_index += field.len;
-            fields.push(field)
         }
-        fields
+
+        Ok(fields)
     }
 }
@@ -1,20 +1,44 @@
 use super::*;
+use data_elements::DataElements;
 use iso_field::FieldCharType;
 use iso_field::FieldSizeType;
 use iso_field::IsoField;
+use iso_msg::{BitmapLayout, SecondaryBitmap};
 use strum_macros;
 
+/// Each category is identified by its mti and function code (DE 24), apart from the online
+/// (ISO 8583:1987) categories, which are identified by their mti only
+///
+/// The `mandatory_*`, `conditional_*` and `prohibited_*` props hold the comma separated DEs and PDS
+/// that are expected on messages of the category, and are used by the validation report. Categories
+/// listing their conditional DEs (or PDS) allow no other DE (or PDS) than the listed ones.
+/// `raw_data_des` lists the DEs that carry a copy of another (binary) message, e.g DE 72 on rejects.
+/// `profiles` restricts the category to some profiles, e.g `"mastercard"`; without it the category
+/// belongs to every profile
 #[derive(Debug, Clone, Serialize, strum_macros::EnumProperty, strum_macros::EnumIter)]
 pub enum Category {
     // File layout messages
-    #[strum(props(mti = "1644", function_code = "697", name = "headers", kind = "file_layout_messages"))]
+    #[strum(props(
+        mti = "1644",
+        function_code = "697",
+        name = "headers",
+        kind = "file_layout_messages",
+        mandatory_des = "024,048,071",
+        prohibited_des = "002,003,004,031",
+        mandatory_pds = "0105",
+        conditional_pds = "0122"
+    ))]
     Header,
 
     #[strum(props(
         mti = "1644",
         function_code = "695",
         name = "trailers",
-        kind = "file_layout_messages"
+        kind = "file_layout_messages",
+        mandatory_des = "024,048,071",
+        prohibited_des = "002,003,004,031",
+        mandatory_pds = "0105,0306",
+        conditional_pds = "0301"
     ))]
     Trailer,
 
@@ -23,7 +47,11 @@ pub enum Category {
         mti = "1240",
         function_code = "200",
         name = "first_presentments",
-        kind = "financial_messages"
+        profiles = "mastercard",
+        kind = "financial_messages",
+        mandatory_des = "002,003,004,012,022,024,026,031,033,042,043,048,049,063,071",
+        conditional_des = "005,006,009,010,014,023,025,030,032,037,038,040,041,050,051,054,055,072,094,095,123,124,125,127",
+        mandatory_pds = "0023"
     ))]
     FirstPresentment,
 
@@ -31,7 +59,10 @@ pub enum Category {
         mti = "1240",
         function_code = "205",
         name = "second_presentments_full",
-        kind = "financial_messages"
+        kind = "financial_messages",
+        mandatory_des = "002,003,004,024,025,031,033,048,049,071",
+        conditional_des = "005,006,009,010,012,022,026,030,038,042,043,050,051,063,072,094,095,123,124,125",
+        mandatory_pds = "0023"
     ))]
     SecondPresentmentFull,
 
@@ -39,7 +70,10 @@ pub enum Category {
         mti = "1240",
         function_code = "282",
         name = "second_presentments_partial",
-        kind = "financial_messages"
+        kind = "financial_messages",
+        mandatory_des = "002,003,004,024,025,030,031,033,048,049,071",
+        conditional_des = "005,006,009,010,012,022,026,038,042,043,050,051,063,072,094,095,123,124,125",
+        mandatory_pds = "0023"
     ))]
     SecondPresentmentPartial,
 
@@ -47,15 +81,57 @@ pub enum Category {
         mti = "1442",
         function_code = "450",
         name = "first_chargebacks",
-        kind = "financial_messages"
+        kind = "financial_messages",
+        mandatory_des = "002,003,004,024,025,030,031,033,048,049,071",
+        conditional_des = "005,006,009,010,012,022,026,038,040,041,042,043,050,051,063,072,093,094,095,100",
+        mandatory_pds = "0023"
     ))]
     FirstChargeback,
 
+    // Elo financial messages, BRL only and without the Banknet reference (DE 63) of Mastercard
+    #[strum(props(
+        mti = "1240",
+        function_code = "200",
+        name = "first_presentments",
+        profiles = "elo",
+        kind = "financial_messages",
+        mandatory_des = "002,003,004,012,022,024,026,031,033,042,043,048,049,071",
+        conditional_des = "005,006,009,010,014,023,025,030,032,037,038,040,041,050,051,054,055,063,072,094,095,123,124,125,127",
+        mandatory_pds = "0023"
+    ))]
+    EloFirstPresentment,
+
+    #[strum(props(
+        mti = "1240",
+        function_code = "210",
+        name = "installment_presentments",
+        profiles = "elo",
+        kind = "financial_messages",
+        mandatory_des = "002,003,004,012,022,024,026,031,033,042,043,048,049,071",
+        conditional_des = "014,023,025,032,037,038,040,041,054,055,063,072,094,095,123,124,125,127",
+        mandatory_pds = "0023,0181"
+    ))]
+    EloInstallmentPresentment,
+
+    #[strum(props(
+        mti = "1442",
+        function_code = "451",
+        name = "installment_chargebacks",
+        profiles = "elo",
+        kind = "financial_messages",
+        mandatory_des = "002,003,004,024,025,030,031,033,048,049,071",
+        conditional_des = "012,022,026,038,040,041,042,043,063,072,093,094,095,100",
+        mandatory_pds = "0023,0181"
+    ))]
+    EloInstallmentChargeback,
+
     #[strum(props(
         mti = "1644",
         function_code = "696",
         name = "financial_details_addenda",
-        kind = "financial_messages"
+        kind = "financial_messages",
+        mandatory_des = "024,033,048,071",
+        conditional_des = "002,031,094"
     ))]
     FinancialDetailAddendum,
 
@@ -64,7 +140,9 @@ pub enum Category {
         mti = "1644",
         function_code = "603",
         name = "retrieval_requests",
-        kind = "retrieval_messages"
+        kind = "retrieval_messages",
+        mandatory_des = "002,024,025,031,048,071",
+        conditional_des = "003,004,012,033,042,043,049,093,094,100"
     ))]
     RetrievalRequest,
 
@@ -72,7 +150,9 @@ pub enum Category {
         mti = "1644",
         function_code = "605",
         name = "retrieval_requests_acknowledgement",
-        kind = "retrieval_messages"
+        kind = "retrieval_messages",
+        mandatory_des = "024,048,071",
+        conditional_des = "002,031,093,094,100"
     ))]
     RetrievalRequestAcknowledgement,
 
@@ -81,7 +161,11 @@ pub enum Category {
         mti = "1644",
         function_code = "685",
         name = "financial_positions",
-        kind = "reconciliation_messages"
+        kind = "reconciliation_messages",
+        mandatory_des = "024,025,048,050,071",
+        conditional_des = "093,100",
+        prohibited_des = "002,031",
+        mandatory_pds = "0300"
     ))]
     FinancialPosition,
 
@@ -89,7 +173,11 @@ pub enum Category {
         mti = "1644",
         function_code = "688",
         name = "settlements",
-        kind = "reconciliation_messages"
+        kind = "reconciliation_messages",
+        mandatory_des = "024,025,048,050,071",
+        conditional_des = "049,093,100",
+        prohibited_des = "002,031",
+        mandatory_pds = "0300"
     ))]
     Settlement,
 
@@ -97,7 +185,11 @@ pub enum Category {
         mti = "1644",
         function_code = "680",
         name = "file_currencies",
-        kind = "reconciliation_messages"
+        kind = "reconciliation_messages",
+        mandatory_des = "024,025,048,049,071",
+        conditional_des = "093,100",
+        prohibited_des = "002,031",
+        mandatory_pds = "0300"
     ))]
     FileCurrency,
 
@@ -106,7 +198,12 @@ pub enum Category {
         mti = "1644",
         function_code = "691",
         name = "message_exceptions",
-        kind = "administrative_messages"
+        kind = "administrative_messages",
+        mandatory_des = "024,048,071,072",
+        conditional_des = "025,093,094,100",
+        mandatory_pds = "0005,0280",
+        conditional_pds = "0006,0138",
+        raw_data_des = "072"
     ))]
     MessageException,
 
@@ -114,7 +211,12 @@ pub enum Category {
         mti = "1644",
         function_code = "699",
         name = "file_rejects",
-        kind = "administrative_messages"
+        kind = "administrative_messages",
+        mandatory_des = "024,048,071,072",
+        conditional_des = "025,093,094,100",
+        mandatory_pds = "0005,0280",
+        conditional_pds = "0006,0138",
+        raw_data_des = "072"
     ))]
     FileReject,
 
@@ -122,7 +224,9 @@ pub enum Category {
         mti = "1644",
         function_code = "693",
         name = "text_messages",
-        kind = "administrative_messages"
+        kind = "administrative_messages",
+        mandatory_des = "024,071,072",
+        conditional_des = "025,048,093,094,100"
     ))]
     TextMessage,
 
@@ -130,7 +234,11 @@ pub enum Category {
         mti = "1644",
         function_code = "640",
         name = "currency_updates",
-        kind = "administrative_messages"
+        profiles = "mastercard",
+        kind = "administrative_messages",
+        mandatory_des = "024,048,050,071",
+        conditional_des = "093,094,100",
+        mandatory_pds = "0164"
     ))]
     CurrencyUpdate,
 
@@ -139,7 +247,9 @@ pub enum Category {
         mti = "1740",
         function_code = "700",
         name = "fee_collections_customer",
-        kind = "fee_collection_messages"
+        kind = "fee_collection_messages",
+        mandatory_des = "003,004,024,025,033,049,071",
+        conditional_des = "002,005,009,031,048,050,063,072,093,094,100"
     ))]
     FeeCollectionCustomer,
 
@@ -147,7 +257,9 @@ pub enum Category {
         mti = "1740",
         function_code = "780",
         name = "fee_collections_customer_return",
-        kind = "fee_collection_messages"
+        kind = "fee_collection_messages",
+        mandatory_des = "003,004,024,025,033,049,071",
+        conditional_des = "002,005,009,031,048,050,063,072,093,094,100"
     ))]
     FeeCollectionCustomerReturn,
 
@@ -155,7 +267,9 @@ pub enum Category {
         mti = "1740",
         function_code = "781",
         name = "fee_collections_customer_resubmission",
-        kind = "fee_collection_messages"
+        kind = "fee_collection_messages",
+        mandatory_des = "003,004,024,025,033,049,071",
+        conditional_des = "002,005,009,031,048,050,063,072,093,094,100"
     ))]
     FeeCollectionCustomerResubmission,
 
@@ -163,7 +277,9 @@ pub enum Category {
         mti = "1740",
         function_code = "782",
         name = "fee_collections_customer_arbitration_return",
-        kind = "fee_collection_messages"
+        kind = "fee_collection_messages",
+        mandatory_des = "003,004,024,025,033,049,071",
+        conditional_des = "002,005,009,031,048,050,063,072,093,094,100"
     ))]
     FeeCollectionCustomerArbitrationReturn,
 
@@ -171,27 +287,457 @@ pub enum Category {
         mti = "1740",
         function_code = "783",
         name = "fee_collections_clearing",
-        kind = "fee_collection_messages"
+        profiles = "mastercard",
+        kind = "fee_collection_messages",
+        mandatory_des = "003,004,024,025,033,049,071",
+        conditional_des = "002,005,009,031,048,050,063,072,093,094,100"
     ))]
     FeeCollectionClearing,
 
+    // Authorization messages (ISO 8583:1987)
+    #[strum(props(
+        mti = "0100",
+        name = "authorization_requests",
+        kind = "authorization_messages",
+        mandatory_des = "003,004,011",
+        conditional_des = "002,007,012,013,014,018,022,023,025,032,035,037,041,042,043,045,049,052,055"
+    ))]
+    AuthorizationRequest,
+
+    #[strum(props(
+        mti = "0110",
+        name = "authorization_responses",
+        kind = "authorization_messages",
+        mandatory_des = "003,004,011,039",
+        conditional_des = "002,007,037,038,041,044,049,055"
+    ))]
+    AuthorizationResponse,
+
+    #[strum(props(
+        mti = "0120",
+        name = "authorization_advices",
+        kind = "authorization_messages",
+        mandatory_des = "003,004,011",
+        conditional_des = "002,007,012,013,014,018,022,023,025,032,037,038,039,041,042,043,049,055"
+    ))]
+    AuthorizationAdvice,
+
+    #[strum(props(
+        mti = "0130",
+        name = "authorization_advice_responses",
+        kind = "authorization_messages",
+        mandatory_des = "003,004,011,039",
+        conditional_des = "002,007,037,041,049"
+    ))]
+    AuthorizationAdviceResponse,
+
+    // Online financial messages (ISO 8583:1987)
+    #[strum(props(
+        mti = "0200",
+        name = "financial_requests",
+        kind = "online_financial_messages",
+        mandatory_des = "003,004,011",
+        conditional_des = "002,007,012,013,014,018,022,023,025,032,035,037,041,042,043,045,049,052,054,055"
+    ))]
+    FinancialRequest,
+
+    #[strum(props(
+        mti = "0210",
+        name = "financial_responses",
+        kind = "online_financial_messages",
+        mandatory_des = "003,004,011,039",
+        conditional_des = "002,007,037,038,041,044,049,054,055"
+    ))]
+    FinancialResponse,
+
+    #[strum(props(
+        mti = "0220",
+        name = "financial_advices",
+        kind = "online_financial_messages",
+        mandatory_des = "003,004,011",
+        conditional_des = "002,007,012,013,014,018,022,023,025,032,037,038,039,041,042,043,049,054,055"
+    ))]
+    FinancialAdvice,
+
+    #[strum(props(
+        mti = "0230",
+        name = "financial_advice_responses",
+        kind = "online_financial_messages",
+        mandatory_des = "003,004,011,039",
+        conditional_des = "002,007,037,041,049"
+    ))]
+    FinancialAdviceResponse,
+
+    // Reversal messages (ISO 8583:1987)
+    #[strum(props(
+        mti = "0400",
+        name = "reversal_requests",
+        kind = "reversal_messages",
+        mandatory_des = "003,004,011,090",
+        conditional_des = "002,007,012,013,032,037,038,039,041,042,049,095"
+    ))]
+    ReversalRequest,
+
+    #[strum(props(
+        mti = "0410",
+        name = "reversal_responses",
+        kind = "reversal_messages",
+        mandatory_des = "003,004,011,039",
+        conditional_des = "002,007,037,041,049,090,095"
+    ))]
+    ReversalResponse,
+
+    #[strum(props(
+        mti = "0420",
+        name = "reversal_advices",
+        kind = "reversal_messages",
+        mandatory_des = "003,004,011,090",
+        conditional_des = "002,007,012,013,032,037,038,039,041,042,049,095"
+    ))]
+    ReversalAdvice,
+
+    #[strum(props(
+        mti = "0430",
+        name = "reversal_advice_responses",
+        kind = "reversal_messages",
+        mandatory_des = "003,004,011,039",
+        conditional_des = "002,007,037,041,049,090,095"
+    ))]
+    ReversalAdviceResponse,
+
+    // Network management messages (ISO 8583:1987)
+    #[strum(props(
+        mti = "0800",
+        name = "network_management_requests",
+        kind = "network_management_messages",
+        mandatory_des = "007,011,070",
+        conditional_des = "033,048,053,096"
+    ))]
+    NetworkManagementRequest,
+
+    #[strum(props(
+        mti = "0810",
+        name = "network_management_responses",
+        kind = "network_management_messages",
+        mandatory_des = "007,011,039,070",
+        conditional_des = "033,048,053,096"
+    ))]
+    NetworkManagementResponse,
+
+    #[strum(props(
+        mti = "0820",
+        name = "network_management_advices",
+        kind = "network_management_messages",
+        mandatory_des = "007,011,070",
+        conditional_des = "033,048,053,096"
+    ))]
+    NetworkManagementAdvice,
     // not intended to be used
     #[strum(props(mti = "unknown", function_code = "unknown", name = "unknown", kind = "unknown"))]
     Unknown,
 }
 
+/// IPM file types, identified by the file type of the file ID (PDS 0105) on the header or,
+/// when it can't be read, by the categories of the file
+///
+/// `prohibited_categories` are the categories that can't show up on each file type, e.g GCMS
+/// generated messages on a member inbound file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, strum_macros::EnumProperty, strum_macros::EnumIter)]
+pub enum FileType {
+    #[strum(props(file_type = "001", name = "outbound_clearing", direction = "outbound"))]
+    OutboundClearing,
+
+    #[strum(props(
+        file_type = "002",
+        name = "inbound_clearing",
+        direction = "inbound",
+        prohibited_categories = "message_exceptions,file_rejects,financial_positions,settlements,file_currencies,currency_updates"
+    ))]
+    InboundClearing,
+
+    #[strum(props(
+        file_type = "003",
+        name = "notification",
+        direction = "outbound",
+        prohibited_categories = "first_presentments,second_presentments_full,second_presentments_partial,first_chargebacks,installment_presentments,installment_chargebacks"
+    ))]
+    Notification,
+
+    // not intended to be used
+    #[strum(props(file_type = "unknown", name = "unknown", direction = "unknown"))]
+    Unknown,
+}
+
+impl FileType {
+    pub fn from_file_type(file_type: &str) -> FileType {
+        FileType::iter()
+            .find(|spec_file_type| spec_file_type.get_str("file_type") == Some(file_type))
+            .unwrap_or(FileType::Unknown)
+    }
+
+    pub fn allows(&self, category: &Category) -> bool {
+        category.get_str("name").map_or(true, |name| self.allows_category_name(name))
+    }
+
+    fn allows_category_name(&self, category_name: &str) -> bool {
+        match self.get_str("prohibited_categories") {
+            Some(prohibited) => !prohibited.split(',').any(|prohibited_name| prohibited_name == category_name),
+            None => true,
+        }
+    }
+
+    /// The narrowest file type (the one prohibiting the most categories) allowing every category
+    /// found on a file, `FileType::Unknown` when the file only has file layout messages
+    pub fn from_categories<'a, I>(category_names: I) -> FileType
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let layout_categories = [Category::Header, Category::Trailer, Category::TextMessage];
+        let category_names: Vec<&str> = category_names
+            .into_iter()
+            .filter(|name| !layout_categories.iter().any(|category| category.get_str("name") == Some(name)))
+            .collect();
+
+        if category_names.is_empty() {
+            return FileType::Unknown;
+        }
+
+        FileType::iter()
+            .filter(|file_type| *file_type != FileType::Unknown)
+            .filter(|file_type| category_names.iter().all(|name| file_type.allows_category_name(name)))
+            .max_by_key(|file_type| {
+                file_type
+                    .get_str("prohibited_categories")
+                    .map_or(0, |list| list.split(',').count())
+            })
+            .unwrap_or(FileType::Unknown)
+    }
+
+    /// Bulk types delivering this file type, the first one being assumed when the file content
+    /// doesn't tell them apart
+    pub fn bulk_types(&self) -> Vec<BulkType> {
+        BulkType::iter()
+            .filter(|bulk_type| bulk_type.file_type() == *self)
+            .collect()
+    }
+}
+
+/// Mastercard bulk types (the bulk id on the file name), each delivering a single file type
+///
+/// `identifying_categories` are the categories that only show up on the files of a bulk type,
+/// telling it apart from the other bulk types of its file type. Bulk types without them are only
+/// told apart by their file name, see `BulkType::from_file_name`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, strum_macros::EnumProperty, strum_macros::EnumIter)]
+pub enum BulkType {
+    #[strum(props(bulk_id = "T112", file_type = "001"))]
+    T112,
+
+    #[strum(props(bulk_id = "R119", file_type = "001", identifying_categories = "currency_updates"))]
+    R119,
+
+    #[strum(props(bulk_id = "T140", file_type = "001"))]
+    T140,
+
+    #[strum(props(bulk_id = "T461", file_type = "001"))]
+    T461,
+
+    #[strum(props(bulk_id = "T464", file_type = "001"))]
+    T464,
+
+    #[strum(props(bulk_id = "R111", file_type = "002"))]
+    R111,
+
+    #[strum(props(bulk_id = "T121", file_type = "003"))]
+    T121,
+
+    #[strum(props(bulk_id = "T113", file_type = "003"))]
+    T113,
+
+    // not intended to be used
+    #[strum(props(bulk_id = "unknown", file_type = "unknown"))]
+    Unknown,
+}
+
+impl BulkType {
+    pub fn file_type(&self) -> FileType {
+        FileType::from_file_type(self.get_str("file_type").unwrap_or_default())
+    }
+
+    /// Finds the bulk id on a file name, e.g `TT112T0.2021-05-14-10-00-00.001`
+    pub fn from_file_name(file_name: &str) -> Option<BulkType> {
+        let file_name = file_name.to_uppercase();
+
+        BulkType::iter()
+            .filter(|bulk_type| *bulk_type != BulkType::Unknown)
+            .find(|bulk_type| bulk_type.get_str("bulk_id").map_or(false, |bulk_id| file_name.contains(bulk_id)))
+    }
+
+    /// The bulk type of a file type whose identifying categories show up on the file, or else the
+    /// first bulk type of the file type
+    pub fn from_categories<'a, I>(file_type: FileType, category_names: I) -> BulkType
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let category_names: Vec<&str> = category_names.into_iter().collect();
+        let bulk_types = file_type.bulk_types();
+        let identified = bulk_types.iter().find(|bulk_type| {
+            bulk_type
+                .get_str("identifying_categories")
+                .map_or(false, |list| list.split(',').any(|name| category_names.contains(&name)))
+        });
+
+        identified
+            .or_else(|| bulk_types.first())
+            .copied()
+            .unwrap_or(BulkType::Unknown)
+    }
+}
+
+/// Presence rules of a set of fields (DEs or PDS) for a given category
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PresenceRules {
+    pub mandatory: Vec<&'static str>,
+    pub conditional: Vec<&'static str>,
+    pub prohibited: Vec<&'static str>,
+}
+
+impl Category {
+    pub fn data_element_rules(&self) -> PresenceRules {
+        PresenceRules {
+            mandatory: self.prop_list("mandatory_des"),
+            conditional: self.prop_list("conditional_des"),
+            prohibited: self.prop_list("prohibited_des"),
+        }
+    }
+
+    pub fn pds_rules(&self) -> PresenceRules {
+        PresenceRules {
+            mandatory: self.prop_list("mandatory_pds"),
+            conditional: self.prop_list("conditional_pds"),
+            prohibited: self.prop_list("prohibited_pds"),
+        }
+    }
+
+    /// DEs whose content is a raw copy of another message, thus not bound to its char type
+    pub fn raw_data_elements(&self) -> Vec<&'static str> {
+        self.prop_list("raw_data_des")
+    }
+
+    /// Online categories have no function code, since they are identified by their mti only
+    pub fn is_online(&self) -> bool {
+        self.get_str("function_code").is_none()
+    }
+
+    pub fn applies_to(&self, profile: Profile) -> bool {
+        match self.get_str("profiles") {
+            Some(profiles) => profiles.split(',').any(|name| Some(name) == profile.get_str("name")),
+            None => true,
+        }
+    }
+
+    fn prop_list(&self, prop: &str) -> Vec<&'static str> {
+        match self.get_str(prop) {
+            Some(list) => list.split(',').collect(),
+            None => vec![],
+        }
+    }
+}
+
+/// Clearing dialect reusing the IPM structure, which drives the categories and PDS names
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, strum_macros::EnumProperty, strum_macros::EnumIter)]
+pub enum Profile {
+    #[default]
+    #[strum(props(name = "mastercard"))]
+    Mastercard,
+    /// Elo and Brazilian domestic networks, carrying BRL installment (parcelado) data on their own
+    /// function codes (210 presentments and 451 chargebacks)
+    #[strum(props(name = "elo"))]
+    Elo,
+}
+
+impl Profile {
+    pub fn from_name(name: &str) -> Option<Profile> {
+        Profile::iter().find(|profile| profile.get_str("name") == Some(name))
+    }
+
+    /// Name of a PDS on this profile, e.g `"File ID"` for `"0105"`
+    pub fn pds_name(&self, pds_id: &str) -> Option<&'static str> {
+        pds::pds_name(*self, pds_id)
+    }
+}
+
 /// Auth spec defines the format of Iso8583 message
+#[derive(Clone)]
 pub struct IsoSpecs {
     pub specs: Vec<IsoField>,
+    pub bitmap_layout: BitmapLayout,
+    /// DEs whose content is parsed as PDS, in the order PDS overflow into them, e.g DE 48, 62, 123,
+    /// 124 and 125 on IPM messages
+    pub pds_data_elements: Vec<&'static str>,
+    pub profile: Profile,
+}
+
+impl Default for IsoSpecs {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IsoSpecs {
     pub fn new() -> IsoSpecs {
         IsoSpecs {
             specs: IsoSpecs::define_specs(),
+            bitmap_layout: BitmapLayout::default(),
+            pds_data_elements: vec!["048", "062", "123", "124", "125"],
+            profile: Profile::Mastercard,
+        }
+    }
+
+    /// Specs for ISO 8583:1987 online messages, e.g 0100 authorizations and 0400 reversals,
+    /// whose secondary bitmap is only present when flagged
+    pub fn iso8583_1987() -> IsoSpecs {
+        IsoSpecs {
+            specs: IsoSpecs::define_iso8583_1987_specs(),
+            bitmap_layout: BitmapLayout {
+                secondary: SecondaryBitmap::WhenFlagged,
+                ..BitmapLayout::default()
+            },
+            pds_data_elements: vec![],
+            profile: Profile::Mastercard,
         }
     }
 
+    /// Specs for Elo and Brazilian domestic clearing files, e.g `parse_file_with_specs(payload, &IsoSpecs::elo())`
+    pub fn elo() -> IsoSpecs {
+        IsoSpecs::new().with_profile(Profile::Elo)
+    }
+
+    /// PDS data elements along with their max length, as taken by `PdsCollection::distribute`
+    pub fn pds_data_element_lengths(&self) -> Vec<(usize, usize)> {
+        self.pds_data_elements
+            .iter()
+            .filter_map(|label_id| {
+                let field_number = DataElements::field_number(label_id)?;
+                self.field(label_id).map(|field| (field_number, field.length))
+            })
+            .collect()
+    }
+
+    pub fn with_profile(mut self, profile: Profile) -> IsoSpecs {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_bitmap_layout(mut self, bitmap_layout: BitmapLayout) -> IsoSpecs {
+        self.bitmap_layout = bitmap_layout;
+        self
+    }
+
+    /// Finds the field definition by its label id, e.g `"024"`
+    pub fn field(&self, label_id: &str) -> Option<&IsoField> {
+        self.specs.iter().find(|field| field.label_id == label_id)
+    }
+
     pub fn define_specs() -> Vec<IsoField> {
         let h = vec![
             IsoField::new(
@@ -1100,4 +1646,916 @@ impl IsoSpecs {
         ];
         h
     }
+
+    /// ISO 8583:1987 data elements, used by online messages (authorization, financial,
+    /// reversal and network management)
+    ///
+    /// As on `define_specs`, index 0 is the mti, index 1 the bitmaps and the remaining indexes match the DE numbers
+    pub fn define_iso8583_1987_specs() -> Vec<IsoField> {
+        vec![
+            IsoField::new(
+                "Message Type Indicator",
+                "mti",
+                FieldCharType::Iso8583_mti,
+                4,
+                FieldSizeType::Fixed,
+            ), // Message Type Indicator
+            IsoField::new(
+                "Bitmaps",
+                "bitmaps", // Primary Bitmap (8 bytes) + DE 1 (8 bytes) when flagged
+                FieldCharType::Iso8583_bmps,
+                16,
+                FieldSizeType::BitMap,
+            ), // Bitmap
+            IsoField::new(
+                "Primary Account Number",
+                "002", // DE 2
+                FieldCharType::Iso8583_ns,
+                19,
+                FieldSizeType::LlVar,
+            ), // Primary Account Number
+            IsoField::new(
+                "Processing Code",
+                "003", // DE 3
+                FieldCharType::Iso8583_n,
+                6,
+                FieldSizeType::Fixed,
+            ), // Processing Code
+            IsoField::new(
+                "Amount, Transaction",
+                "004", // DE 4
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ), // Amount, Transaction
+            IsoField::new(
+                "Amount, Settlement",
+                "005", // DE 5
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ), // Amount, Settlement
+            IsoField::new(
+                "Amount, Cardholder Billing",
+                "006", // DE 6
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ), // Amount, Cardholder Billing
+            IsoField::new(
+                "Transmission Date and Time",
+                "007", // DE 7
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Transmission Date and Time
+            IsoField::new(
+                "Amount, Cardholder Billing Fee",
+                "008", // DE 8
+                FieldCharType::Iso8583_n,
+                8,
+                FieldSizeType::Fixed,
+            ), // Amount, Cardholder Billing Fee
+            IsoField::new(
+                "Conversion Rate, Settlement",
+                "009", // DE 9
+                FieldCharType::Iso8583_n,
+                8,
+                FieldSizeType::Fixed,
+            ), // Conversion Rate, Settlement
+            IsoField::new(
+                "Conversion Rate, Cardholder Billing",
+                "010", // DE 10
+                FieldCharType::Iso8583_n,
+                8,
+                FieldSizeType::Fixed,
+            ), // Conversion Rate, Cardholder Billing
+            IsoField::new(
+                "Systems Trace Audit Number",
+                "011", // DE 11
+                FieldCharType::Iso8583_n,
+                6,
+                FieldSizeType::Fixed,
+            ), // Systems Trace Audit Number
+            IsoField::new(
+                "Time, Local Transaction",
+                "012", // DE 12
+                FieldCharType::Iso8583_n,
+                6,
+                FieldSizeType::Fixed,
+            ), // Time, Local Transaction
+            IsoField::new(
+                "Date, Local Transaction",
+                "013", // DE 13
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Date, Local Transaction
+            IsoField::new(
+                "Date, Expiration",
+                "014", // DE 14
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Date, Expiration
+            IsoField::new(
+                "Date, Settlement",
+                "015", // DE 15
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Date, Settlement
+            IsoField::new(
+                "Date, Conversion",
+                "016", // DE 16
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Date, Conversion
+            IsoField::new(
+                "Date, Capture",
+                "017", // DE 17
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Date, Capture
+            IsoField::new(
+                "Merchant Type",
+                "018", // DE 18
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Merchant Type
+            IsoField::new(
+                "Acquiring Institution Country Code",
+                "019", // DE 19
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Acquiring Institution Country Code
+            IsoField::new(
+                "Primary Account Number Extended, Country Code",
+                "020", // DE 20
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Primary Account Number Extended, Country Code
+            IsoField::new(
+                "Forwarding Institution Country Code",
+                "021", // DE 21
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Forwarding Institution Country Code
+            IsoField::new(
+                "Point of Service Entry Mode",
+                "022", // DE 22
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Point of Service Entry Mode
+            IsoField::new(
+                "Application PAN Sequence Number",
+                "023", // DE 23
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Application PAN Sequence Number
+            IsoField::new(
+                "Network International Identifier",
+                "024", // DE 24
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Network International Identifier
+            IsoField::new(
+                "Point of Service Condition Code",
+                "025", // DE 25
+                FieldCharType::Iso8583_n,
+                2,
+                FieldSizeType::Fixed,
+            ), // Point of Service Condition Code
+            IsoField::new(
+                "Point of Service Capture Code",
+                "026", // DE 26
+                FieldCharType::Iso8583_n,
+                2,
+                FieldSizeType::Fixed,
+            ), // Point of Service Capture Code
+            IsoField::new(
+                "Authorizing Identification Response Length",
+                "027", // DE 27
+                FieldCharType::Iso8583_n,
+                1,
+                FieldSizeType::Fixed,
+            ), // Authorizing Identification Response Length
+            IsoField::new(
+                "Amount, Transaction Fee",
+                "028", // DE 28
+                FieldCharType::Iso8583_xn,
+                9,
+                FieldSizeType::Fixed,
+            ), // Amount, Transaction Fee
+            IsoField::new(
+                "Amount, Settlement Fee",
+                "029", // DE 29
+                FieldCharType::Iso8583_xn,
+                9,
+                FieldSizeType::Fixed,
+            ), // Amount, Settlement Fee
+            IsoField::new(
+                "Amount, Transaction Processing Fee",
+                "030", // DE 30
+                FieldCharType::Iso8583_xn,
+                9,
+                FieldSizeType::Fixed,
+            ), // Amount, Transaction Processing Fee
+            IsoField::new(
+                "Amount, Settlement Processing Fee",
+                "031", // DE 31
+                FieldCharType::Iso8583_xn,
+                9,
+                FieldSizeType::Fixed,
+            ), // Amount, Settlement Processing Fee
+            IsoField::new(
+                "Acquiring Institution Identification Code",
+                "032", // DE 32
+                FieldCharType::Iso8583_n,
+                11,
+                FieldSizeType::LlVar,
+            ), // Acquiring Institution Identification Code
+            IsoField::new(
+                "Forwarding Institution Identification Code",
+                "033", // DE 33
+                FieldCharType::Iso8583_n,
+                11,
+                FieldSizeType::LlVar,
+            ), // Forwarding Institution Identification Code
+            IsoField::new(
+                "Primary Account Number, Extended",
+                "034", // DE 34
+                FieldCharType::Iso8583_ns,
+                28,
+                FieldSizeType::LlVar,
+            ), // Primary Account Number, Extended
+            IsoField::new(
+                "Track 2 Data",
+                "035", // DE 35
+                FieldCharType::Iso8583_z,
+                37,
+                FieldSizeType::LlVar,
+            ), // Track 2 Data
+            IsoField::new(
+                "Track 3 Data",
+                "036", // DE 36
+                FieldCharType::Iso8583_ns, // Using ns since it may not fit into an u64
+                104,
+                FieldSizeType::LllVar,
+            ), // Track 3 Data
+            IsoField::new(
+                "Retrieval Reference Number",
+                "037", // DE 37
+                FieldCharType::Iso8583_an,
+                12,
+                FieldSizeType::Fixed,
+            ), // Retrieval Reference Number
+            IsoField::new(
+                "Authorization Identification Response",
+                "038", // DE 38
+                FieldCharType::Iso8583_an,
+                6,
+                FieldSizeType::Fixed,
+            ), // Authorization Identification Response
+            IsoField::new(
+                "Response Code",
+                "039", // DE 39
+                FieldCharType::Iso8583_an,
+                2,
+                FieldSizeType::Fixed,
+            ), // Response Code
+            IsoField::new(
+                "Service Restriction Code",
+                "040", // DE 40
+                FieldCharType::Iso8583_an,
+                3,
+                FieldSizeType::Fixed,
+            ), // Service Restriction Code
+            IsoField::new(
+                "Card Acceptor Terminal Identification",
+                "041", // DE 41
+                FieldCharType::Iso8583_ans,
+                8,
+                FieldSizeType::Fixed,
+            ), // Card Acceptor Terminal Identification
+            IsoField::new(
+                "Card Acceptor Identification Code",
+                "042", // DE 42
+                FieldCharType::Iso8583_ans,
+                15,
+                FieldSizeType::Fixed,
+            ), // Card Acceptor Identification Code
+            IsoField::new(
+                "Card Acceptor Name/Location",
+                "043", // DE 43
+                FieldCharType::Iso8583_ans,
+                40,
+                FieldSizeType::Fixed,
+            ), // Card Acceptor Name/Location
+            IsoField::new(
+                "Additional Response Data",
+                "044", // DE 44
+                FieldCharType::Iso8583_an,
+                25,
+                FieldSizeType::LlVar,
+            ), // Additional Response Data
+            IsoField::new(
+                "Track 1 Data",
+                "045", // DE 45
+                FieldCharType::Iso8583_ans,
+                76,
+                FieldSizeType::LlVar,
+            ), // Track 1 Data
+            IsoField::new(
+                "Additional Data - ISO",
+                "046", // DE 46
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Additional Data - ISO
+            IsoField::new(
+                "Additional Data - National",
+                "047", // DE 47
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Additional Data - National
+            IsoField::new(
+                "Additional Data - Private",
+                "048", // DE 48
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Additional Data - Private
+            IsoField::new(
+                "Currency Code, Transaction",
+                "049", // DE 49
+                FieldCharType::Iso8583_an,
+                3,
+                FieldSizeType::Fixed,
+            ), // Currency Code, Transaction
+            IsoField::new(
+                "Currency Code, Settlement",
+                "050", // DE 50
+                FieldCharType::Iso8583_an,
+                3,
+                FieldSizeType::Fixed,
+            ), // Currency Code, Settlement
+            IsoField::new(
+                "Currency Code, Cardholder Billing",
+                "051", // DE 51
+                FieldCharType::Iso8583_an,
+                3,
+                FieldSizeType::Fixed,
+            ), // Currency Code, Cardholder Billing
+            IsoField::new(
+                "Personal Identification Number Data",
+                "052", // DE 52
+                FieldCharType::Iso8583_b,
+                8,
+                FieldSizeType::Fixed,
+            ), // Personal Identification Number Data
+            IsoField::new(
+                "Security Related Control Information",
+                "053", // DE 53
+                FieldCharType::Iso8583_n,
+                16,
+                FieldSizeType::Fixed,
+            ), // Security Related Control Information
+            IsoField::new(
+                "Additional Amounts",
+                "054", // DE 54
+                FieldCharType::Iso8583_an,
+                120,
+                FieldSizeType::LllVar,
+            ), // Additional Amounts
+            IsoField::new(
+                "Reserved ISO",
+                "055", // DE 55
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved ISO",
+                "056", // DE 56
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved National",
+                "057", // DE 57
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "058", // DE 58
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "059", // DE 59
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "060", // DE 60
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved Private",
+                "061", // DE 61
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "062", // DE 62
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "063", // DE 63
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Message Authentication Code",
+                "064", // DE 64
+                FieldCharType::Iso8583_b,
+                8,
+                FieldSizeType::Fixed,
+            ), // Message Authentication Code
+            IsoField::new(
+                "Bitmap, Extended",
+                "065", // DE 65
+                FieldCharType::Iso8583_b,
+                1,
+                FieldSizeType::Fixed,
+            ), // Bitmap, Extended
+            IsoField::new(
+                "Settlement Code",
+                "066", // DE 66
+                FieldCharType::Iso8583_n,
+                1,
+                FieldSizeType::Fixed,
+            ), // Settlement Code
+            IsoField::new(
+                "Extended Payment Code",
+                "067", // DE 67
+                FieldCharType::Iso8583_n,
+                2,
+                FieldSizeType::Fixed,
+            ), // Extended Payment Code
+            IsoField::new(
+                "Receiving Institution Country Code",
+                "068", // DE 68
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Receiving Institution Country Code
+            IsoField::new(
+                "Settlement Institution Country Code",
+                "069", // DE 69
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Settlement Institution Country Code
+            IsoField::new(
+                "Network Management Information Code",
+                "070", // DE 70
+                FieldCharType::Iso8583_n,
+                3,
+                FieldSizeType::Fixed,
+            ), // Network Management Information Code
+            IsoField::new(
+                "Message Number",
+                "071", // DE 71
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Message Number
+            IsoField::new(
+                "Message Number, Last",
+                "072", // DE 72
+                FieldCharType::Iso8583_n,
+                4,
+                FieldSizeType::Fixed,
+            ), // Message Number, Last
+            IsoField::new(
+                "Date, Action",
+                "073", // DE 73
+                FieldCharType::Iso8583_n,
+                6,
+                FieldSizeType::Fixed,
+            ), // Date, Action
+            IsoField::new(
+                "Credits, Number",
+                "074", // DE 74
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Credits, Number
+            IsoField::new(
+                "Credits, Reversal Number",
+                "075", // DE 75
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Credits, Reversal Number
+            IsoField::new(
+                "Debits, Number",
+                "076", // DE 76
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Debits, Number
+            IsoField::new(
+                "Debits, Reversal Number",
+                "077", // DE 77
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Debits, Reversal Number
+            IsoField::new(
+                "Transfer, Number",
+                "078", // DE 78
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Transfer, Number
+            IsoField::new(
+                "Transfer, Reversal Number",
+                "079", // DE 79
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Transfer, Reversal Number
+            IsoField::new(
+                "Inquiries, Number",
+                "080", // DE 80
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Inquiries, Number
+            IsoField::new(
+                "Authorizations, Number",
+                "081", // DE 81
+                FieldCharType::Iso8583_n,
+                10,
+                FieldSizeType::Fixed,
+            ), // Authorizations, Number
+            IsoField::new(
+                "Credits, Processing Fee Amount",
+                "082", // DE 82
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ), // Credits, Processing Fee Amount
+            IsoField::new(
+                "Credits, Transaction Fee Amount",
+                "083", // DE 83
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ), // Credits, Transaction Fee Amount
+            IsoField::new(
+                "Debits, Processing Fee Amount",
+                "084", // DE 84
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ), // Debits, Processing Fee Amount
+            IsoField::new(
+                "Debits, Transaction Fee Amount",
+                "085", // DE 85
+                FieldCharType::Iso8583_n,
+                12,
+                FieldSizeType::Fixed,
+            ), // Debits, Transaction Fee Amount
+            IsoField::new(
+                "Credits, Amount",
+                "086", // DE 86
+                FieldCharType::Iso8583_n,
+                16,
+                FieldSizeType::Fixed,
+            ), // Credits, Amount
+            IsoField::new(
+                "Credits, Reversal Amount",
+                "087", // DE 87
+                FieldCharType::Iso8583_n,
+                16,
+                FieldSizeType::Fixed,
+            ), // Credits, Reversal Amount
+            IsoField::new(
+                "Debits, Amount",
+                "088", // DE 88
+                FieldCharType::Iso8583_n,
+                16,
+                FieldSizeType::Fixed,
+            ), // Debits, Amount
+            IsoField::new(
+                "Debits, Reversal Amount",
+                "089", // DE 89
+                FieldCharType::Iso8583_n,
+                16,
+                FieldSizeType::Fixed,
+            ), // Debits, Reversal Amount
+            IsoField::new(
+                "Original Data Elements",
+                "090", // DE 90
+                FieldCharType::Iso8583_ns, // Using ns since it may not fit into an u64
+                42,
+                FieldSizeType::Fixed,
+            ), // Original Data Elements
+            IsoField::new(
+                "File Update Code",
+                "091", // DE 91
+                FieldCharType::Iso8583_an,
+                1,
+                FieldSizeType::Fixed,
+            ), // File Update Code
+            IsoField::new(
+                "File Security Code",
+                "092", // DE 92
+                FieldCharType::Iso8583_an,
+                2,
+                FieldSizeType::Fixed,
+            ), // File Security Code
+            IsoField::new(
+                "Response Indicator",
+                "093", // DE 93
+                FieldCharType::Iso8583_an,
+                5,
+                FieldSizeType::Fixed,
+            ), // Response Indicator
+            IsoField::new(
+                "Service Indicator",
+                "094", // DE 94
+                FieldCharType::Iso8583_an,
+                7,
+                FieldSizeType::Fixed,
+            ), // Service Indicator
+            IsoField::new(
+                "Replacement Amounts",
+                "095", // DE 95
+                FieldCharType::Iso8583_an,
+                42,
+                FieldSizeType::Fixed,
+            ), // Replacement Amounts
+            IsoField::new(
+                "Message Security Code",
+                "096", // DE 96
+                FieldCharType::Iso8583_b,
+                8,
+                FieldSizeType::Fixed,
+            ), // Message Security Code
+            IsoField::new(
+                "Amount, Net Settlement",
+                "097", // DE 97
+                FieldCharType::Iso8583_xn,
+                17,
+                FieldSizeType::Fixed,
+            ), // Amount, Net Settlement
+            IsoField::new(
+                "Payee",
+                "098", // DE 98
+                FieldCharType::Iso8583_ans,
+                25,
+                FieldSizeType::Fixed,
+            ), // Payee
+            IsoField::new(
+                "Settlement Institution Identification Code",
+                "099", // DE 99
+                FieldCharType::Iso8583_n,
+                11,
+                FieldSizeType::LlVar,
+            ), // Settlement Institution Identification Code
+            IsoField::new(
+                "Receiving Institution Identification Code",
+                "100", // DE 100
+                FieldCharType::Iso8583_n,
+                11,
+                FieldSizeType::LlVar,
+            ), // Receiving Institution Identification Code
+            IsoField::new(
+                "File Name",
+                "101", // DE 101
+                FieldCharType::Iso8583_ans,
+                17,
+                FieldSizeType::LlVar,
+            ), // File Name
+            IsoField::new(
+                "Account Identification 1",
+                "102", // DE 102
+                FieldCharType::Iso8583_ans,
+                28,
+                FieldSizeType::LlVar,
+            ), // Account Identification 1
+            IsoField::new(
+                "Account Identification 2",
+                "103", // DE 103
+                FieldCharType::Iso8583_ans,
+                28,
+                FieldSizeType::LlVar,
+            ), // Account Identification 2
+            IsoField::new(
+                "Transaction Description",
+                "104", // DE 104
+                FieldCharType::Iso8583_ans,
+                100,
+                FieldSizeType::LllVar,
+            ), // Transaction Description
+            IsoField::new(
+                "Reserved ISO",
+                "105", // DE 105
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved ISO",
+                "106", // DE 106
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved ISO",
+                "107", // DE 107
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved ISO",
+                "108", // DE 108
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved ISO",
+                "109", // DE 109
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved ISO",
+                "110", // DE 110
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved ISO",
+                "111", // DE 111
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved ISO
+            IsoField::new(
+                "Reserved National",
+                "112", // DE 112
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "113", // DE 113
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "114", // DE 114
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "115", // DE 115
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "116", // DE 116
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "117", // DE 117
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "118", // DE 118
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved National",
+                "119", // DE 119
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved National
+            IsoField::new(
+                "Reserved Private",
+                "120", // DE 120
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "121", // DE 121
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "122", // DE 122
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "123", // DE 123
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "124", // DE 124
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "125", // DE 125
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "126", // DE 126
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Reserved Private",
+                "127", // DE 127
+                FieldCharType::Iso8583_ans,
+                999,
+                FieldSizeType::LllVar,
+            ), // Reserved Private
+            IsoField::new(
+                "Message Authentication Code",
+                "128", // DE 128
+                FieldCharType::Iso8583_b,
+                8,
+                FieldSizeType::Fixed,
+            ), // Message Authentication Code
+        ]
+    }
 }
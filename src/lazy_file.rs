@@ -0,0 +1,221 @@
+use crate::file_utils::{self, RecordsBoundaries, BLOCK_DATA_SIZE, BLOCK_SIZE, RDW_SIZE};
+use crate::iso_specs::IsoSpecs;
+use crate::message_ref::MessageRefs;
+use crate::{parse_message, Message};
+use eyre::{eyre, Result};
+use std::borrow::Cow;
+use std::ops::{Deref, Range};
+
+/// Bytes of a `LazyFile`, either read into memory or mapped from the disk
+enum Source {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for Source {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Source::Owned(payload) => payload,
+            #[cfg(feature = "mmap")]
+            Source::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+/// A file whose record offsets are indexed up front, decoding each `Message` only when accessed
+///
+/// RDW files are indexed by walking their RDWs, skipping the 1014 blocking on the way, and files
+/// without RDW by walking their messages bitmaps and length prefixes
+pub struct LazyFile {
+    source: Source,
+    specs: IsoSpecs,
+    blocked: bool,
+    /// records positions, as if the file had no blocking
+    records: RecordsBoundaries,
+}
+
+impl LazyFile {
+    pub fn from_bytes(payload: Vec<u8>, specs: IsoSpecs) -> Result<LazyFile> {
+        LazyFile::index(Source::Owned(payload), specs)
+    }
+
+    /// Maps the file into memory instead of reading it, so only the accessed pages are loaded
+    #[cfg(feature = "mmap")]
+    pub fn open(file_name: &str, specs: IsoSpecs) -> Result<LazyFile> {
+        let file = std::fs::File::open(file_name).map_err(|e| eyre!("unable to open {}: {}", file_name, e))?;
+
+        // the mapping is read only, changing the file while it's mapped is undefined behavior
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+
+        LazyFile::index(Source::Mapped(mmap), specs)
+    }
+
+    fn index(source: Source, specs: IsoSpecs) -> Result<LazyFile> {
+        let has_rdw = file_utils::has_rdw_or_block(&source);
+        let blocked = has_rdw && file_utils::is_blocked(&source);
+
+        let mut lazy_file = LazyFile {
+            source,
+            specs,
+            blocked,
+            records: vec![],
+        };
+
+        lazy_file.records = if has_rdw {
+            lazy_file.index_rdw_records()?
+        } else {
+            lazy_file.index_messages()?
+        };
+
+        Ok(lazy_file)
+    }
+
+    fn index_rdw_records(&self) -> Result<RecordsBoundaries> {
+        let mut records = vec![];
+        let mut position = 0usize;
+        let length = self.deblocked_len();
+
+        while position + RDW_SIZE <= length {
+            let rdw = self.read(position..position + RDW_SIZE);
+            let record_size = u32::from_be_bytes([rdw[0], rdw[1], rdw[2], rdw[3]]) as usize;
+            if record_size == 0 {
+                break;
+            }
+
+            position += RDW_SIZE;
+            if position + record_size > length {
+                return Err(eyre!("record at position {} goes beyond the end of the file", position));
+            }
+
+            records.push(position..position + record_size);
+            position += record_size;
+        }
+
+        Ok(records)
+    }
+
+    fn index_messages(&self) -> Result<RecordsBoundaries> {
+        let mut position = 0usize;
+
+        MessageRefs::new(&self.source, &self.specs)
+            .map(|message_ref| {
+                let record = position..position + message_ref?.length();
+                position = record.end;
+                Ok(record)
+            })
+            .collect()
+    }
+
+    fn deblocked_len(&self) -> usize {
+        match self.blocked {
+            true => self.source.len() / BLOCK_SIZE * BLOCK_DATA_SIZE,
+            false => self.source.len(),
+        }
+    }
+
+    /// Reads a range of the file as if it had no blocking, only copying ranges crossing a block end
+    fn read(&self, range: Range<usize>) -> Cow<'_, [u8]> {
+        if !self.blocked {
+            return Cow::Borrowed(&self.source[range]);
+        }
+
+        let raw_position = |position: usize| position / BLOCK_DATA_SIZE * BLOCK_SIZE + position % BLOCK_DATA_SIZE;
+
+        if range.is_empty() || range.start / BLOCK_DATA_SIZE == (range.end - 1) / BLOCK_DATA_SIZE {
+            return Cow::Borrowed(&self.source[raw_position(range.start)..raw_position(range.start) + range.len()]);
+        }
+
+        let mut bytes = Vec::with_capacity(range.len());
+        let mut position = range.start;
+        while position < range.end {
+            let block_end = (position / BLOCK_DATA_SIZE + 1) * BLOCK_DATA_SIZE;
+            let chunk_end = block_end.min(range.end);
+            bytes.extend_from_slice(&self.source[raw_position(position)..raw_position(position) + chunk_end - position]);
+            position = chunk_end;
+        }
+
+        Cow::Owned(bytes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Raw bytes of a message, without its RDW
+    pub fn record(&self, index: usize) -> Option<Cow<'_, [u8]>> {
+        self.records.get(index).map(|record| self.read(record.clone()))
+    }
+
+    /// Decodes a single message, e.g `file.message(1_000_000)` without decoding the ones before it
+    pub fn message(&self, index: usize) -> Result<Message> {
+        let record = self
+            .record(index)
+            .ok_or_else(|| eyre!("message {} out of range, the file has {} messages", index, self.len()))?;
+
+        parse_message(&record, &self.specs)
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = Result<Message>> + '_ {
+        (0..self.len()).map(|index| self.message(index))
+    }
+}
+
+#[test]
+fn test_lazy_file_over_blocked_records() {
+    use crate::builder::MessageBuilder;
+
+    let specs = IsoSpecs::new();
+    let records: Vec<Vec<u8>> = (0..40)
+        .map(|index| {
+            let message = MessageBuilder::new(&specs)
+                .mti("1240")
+                .de(24, "200")
+                .de(71, &(index + 1).to_string())
+                .pds("0023", "POI")
+                .build()
+                .unwrap();
+            crate::writer::encode_message(&specs, &message).unwrap()
+        })
+        .collect();
+
+    let payload = file_utils::block(file_utils::add_rdw_to(&records));
+    let lazy_file = LazyFile::from_bytes(payload, IsoSpecs::new()).unwrap();
+
+    assert_eq!(lazy_file.len(), 40);
+    for (index, record) in records.iter().enumerate() {
+        assert_eq!(lazy_file.record(index).unwrap().as_ref(), record.as_slice());
+    }
+    assert_eq!(lazy_file.message(39).unwrap().data_elements["071"].get_string(), "40");
+    assert!(lazy_file.message(40).is_err());
+}
+
+#[test]
+fn test_lazy_file_over_unblocked_records_of_a_block_length() {
+    use crate::builder::MessageBuilder;
+
+    let specs = IsoSpecs::new();
+    let message = MessageBuilder::new(&specs)
+        .mti("1240")
+        .de(24, "200")
+        .pds("0146", &"X".repeat(990))
+        .build()
+        .unwrap();
+    let mut record = crate::writer::encode_message(&specs, &message).unwrap();
+
+    // a single record filling exactly two blocks, without the 1014 blocking
+    record.resize(2 * BLOCK_SIZE - 2 * RDW_SIZE, b' ');
+    let payload = file_utils::add_rdw_to(std::slice::from_ref(&record));
+    assert_eq!(payload.len() % BLOCK_SIZE, 0);
+
+    let lazy_file = LazyFile::from_bytes(payload, IsoSpecs::new()).unwrap();
+    assert_eq!(lazy_file.len(), 1);
+    assert_eq!(lazy_file.record(0).unwrap().as_ref(), record.as_slice());
+    assert_eq!(lazy_file.message(0).unwrap().pds, message.pds);
+}
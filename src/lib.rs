@@ -6,13 +6,28 @@ extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
+pub mod base2;
+pub mod builder;
+pub mod data_elements;
+pub mod decoders;
+pub mod diff;
+pub mod encoding;
+pub mod file_ops;
 pub mod file_utils;
+pub mod framing;
 pub mod iso_field;
 pub mod iso_msg;
 pub mod iso_specs;
+pub mod lazy_file;
+pub mod lossless;
+pub mod message_ref;
+pub mod mpe;
 pub mod pds;
+pub mod query;
+pub mod validation;
+pub mod writer;
 
-use crate::iso_specs::Category;
+use crate::iso_specs::{BulkType, Category, FileType, Profile};
 use eyre::{eyre, Result};
 use std::collections::HashMap;
 use std::fmt;
@@ -52,24 +67,149 @@ pub struct Message {
     pub mti: String,
     pub category: Category,
     pub primary_bitmap: [u8; 8],
-    pub data_elements: HashMap<String, iso_field::IPMValue>,
-    pub pds: HashMap<String, String>,
+    pub data_elements: data_elements::DataElements,
+    pub pds: pds::PdsCollection,
+    /// the record the message was parsed from, only kept by `parse_file_lossless`
+    #[serde(skip)]
+    pub raw: Option<Box<lossless::RawMessage>>,
+}
+
+/// A message serialized along with the data decoded from its PDS, see `Message::decoded`
+#[derive(Serialize)]
+pub struct DecodedMessage<'a> {
+    #[serde(flatten)]
+    pub message: &'a Message,
+    /// `None` when missing or malformed
+    pub installment: Option<decoders::Installment>,
 }
 
 impl Message {
-    fn get_category(mti: &str, ipm_function_code: &iso_field::IPMValue) -> Category {
-        let mut category = Category::Unknown;
+    /// Value of a DE by its number, e.g `message.de(24)` for the function code
+    pub fn de(&self, field_number: usize) -> Option<&iso_field::IPMValue> {
+        self.data_elements.de(field_number)
+    }
+
+    /// Decodes DE 2, returns `None` when the message has no PAN
+    pub fn pan(&self) -> Result<Option<decoders::Pan>> {
+        self.de(2)
+            .map(|value| decoders::Pan::decode(&value.get_string()))
+            .transpose()
+    }
 
-        let function_code = ipm_function_code.get_string();
+    /// Decodes DE 31, returns `None` when the message has no acquirer reference data
+    pub fn arn(&self) -> Result<Option<decoders::Arn>> {
+        self.de(31)
+            .map(|value| decoders::Arn::decode(&value.get_string()))
+            .transpose()
+    }
+
+    /// Decodes PDS 0181, returns `None` when the message isn't an installment purchase
+    pub fn installment(&self) -> Result<Option<decoders::Installment>> {
+        self.pds
+            .get("0181")
+            .map(|value| decoders::Installment::decode(value))
+            .transpose()
+    }
 
-        for spec_category in Category::iter() {
-            if spec_category.get_str("mti") == Some(mti)
-                && spec_category.get_str("function_code") == Some(&function_code) {
-                category = spec_category;
+    /// Pairs the message with its decoded installment data, e.g to export both as JSON
+    pub fn decoded(&self) -> DecodedMessage<'_> {
+        DecodedMessage {
+            message: self,
+            installment: self.installment().ok().flatten(),
+        }
+    }
+
+    /// Decodes PDS 0105, returns `None` when the message isn't a header or trailer
+    pub fn file_id(&self) -> Result<Option<decoders::FileId>> {
+        self.pds
+            .get("0105")
+            .map(|value| decoders::FileId::decode(value))
+            .transpose()
+    }
+
+    /// Sets a PDS and rebuilds the data element carrying it from its PDS, in the order they were read
+    ///
+    /// New PDS go on the data element of the last one. The message is left unchanged when that data
+    /// element would exceed its 999 characters, see `distribute_pds` to overflow it into the next ones
+    pub fn set_pds(&mut self, pds_id: &str, value: &str) -> Result<()> {
+        if value.len() > pds::PDS_MAX_LEN {
+            return Err(eyre!("pds {} has {} characters, max is {}", pds_id, value.len(), pds::PDS_MAX_LEN));
+        }
+
+        let previous_value = self.pds.insert(pds_id, value);
+        let data_element = self.pds.entry(pds_id).map_or(48, |entry| entry.data_element);
+        let encoded = self.pds.encode(data_element);
+
+        if encoded.len() > pds::PDS_DATA_ELEMENT_MAX_LEN {
+            match previous_value {
+                Some(previous_value) => self.pds.insert(pds_id, &previous_value),
+                None => self.pds.remove(pds_id),
+            };
+            return Err(eyre!(
+                "pds {} doesn't fit on DE {}, which would have {} characters",
+                pds_id,
+                data_element,
+                encoded.len()
+            ));
+        }
+
+        self.data_elements.set_de(data_element, iso_field::IPMValue::String(encoded));
+        Ok(())
+    }
+
+    /// Removes a PDS and rebuilds the data element that carried it, removing the data element once
+    /// it's left empty
+    pub fn remove_pds(&mut self, pds_id: &str) -> Option<String> {
+        let data_element = self.pds.entry(pds_id)?.data_element;
+        let value = self.pds.remove(pds_id);
+
+        let encoded = self.pds.encode(data_element);
+        if encoded.is_empty() {
+            self.data_elements.remove_de(data_element);
+        } else {
+            self.data_elements.set_de(data_element, iso_field::IPMValue::String(encoded));
+        }
+
+        value
+    }
+
+    /// Lays the PDS out again over the PDS data elements of the specs, e.g overflowing DE 48 into
+    /// DE 62, 123, 124 and 125, and rebuilds them. Data elements left without PDS are removed
+    pub fn distribute_pds(&mut self, specs: &iso_specs::IsoSpecs) -> Result<()> {
+        let data_element_lengths = specs.pds_data_element_lengths();
+        self.pds.distribute(&data_element_lengths)?;
+
+        for (data_element, _) in data_element_lengths {
+            let encoded = self.pds.encode(data_element);
+            if encoded.is_empty() {
+                self.data_elements.remove_de(data_element);
+            } else {
+                self.data_elements.set_de(data_element, iso_field::IPMValue::String(encoded));
             }
-        };
+        }
 
-        category
+        Ok(())
+    }
+
+    /// Names the PDS of the message according to the profile, keeping the id of the unknown ones
+    pub fn named_pds(&self, profile: Profile) -> HashMap<String, String> {
+        self.pds
+            .iter()
+            .map(|(pds_id, value)| (profile.pds_name(pds_id).unwrap_or(pds_id).to_string(), value.clone()))
+            .collect()
+    }
+
+    /// Finds the category of the profile by mti and function code (DE 24), which may be missing on online messages
+    fn get_category(profile: Profile, mti: &str, ipm_function_code: Option<&iso_field::IPMValue>) -> Category {
+        let function_code = ipm_function_code.map(|value| value.get_string());
+
+        Category::iter()
+            .find(|spec_category| {
+                spec_category.applies_to(profile)
+                    && spec_category.get_str("mti") == Some(mti)
+                    && (spec_category.is_online() || spec_category.get_str("function_code") == function_code.as_deref())
+            })
+            .unwrap_or(Category::Unknown)
     }
 }
 
@@ -77,6 +217,12 @@ impl Message {
 pub struct Iso8583File {
     pub messages: Vec<Message>,
     pub categories_indexes: HashMap<String, Vec<usize>>,
+    /// built on demand with `build_index`, keyed by the indexed DE or PDS and then by its values
+    #[serde(skip)]
+    value_indexes: HashMap<query::Selector, HashMap<String, Vec<usize>>>,
+    /// how the records were laid out and the specs they were parsed with, only kept by `parse_file_lossless`
+    #[serde(skip)]
+    lossless: Option<(lossless::FileLayout, iso_specs::IsoSpecs)>,
 }
 
 impl fmt::Debug for Iso8583File {
@@ -101,6 +247,8 @@ impl Iso8583File {
         let mut parsed_file = Iso8583File {
             messages,
             categories_indexes: HashMap::new(),
+            value_indexes: HashMap::new(),
+            lossless: None,
         };
 
         parsed_file.assign_messages_categories()?;
@@ -108,6 +256,31 @@ impl Iso8583File {
         Ok(parsed_file)
     }
 
+    /// Decodes the file ID (PDS 0105) of the file header, returns `None` when there's no header
+    pub fn file_id(&self) -> Result<Option<decoders::FileId>> {
+        match self.categories_indexes.get("headers").and_then(|indexes| indexes.first()) {
+            Some(&index) => self.messages[index].file_id(),
+            None => Ok(None),
+        }
+    }
+
+    /// Classifies the file by its header file ID or, when the header is missing or its file ID
+    /// can't be decoded, by its categories (see `FileType::from_categories`)
+    pub fn file_type(&self) -> FileType {
+        match self.file_id() {
+            Ok(Some(file_id)) => FileType::from_file_type(&file_id.file_type),
+            _ => FileType::from_categories(self.categories_indexes.keys().map(String::as_str)),
+        }
+    }
+
+    /// Classifies the file into a bulk type of its file type, by the categories it carries
+    ///
+    /// Bulk types that share their file type and categories can only be told apart by the file
+    /// name, see `BulkType::from_file_name`
+    pub fn bulk_type(&self) -> BulkType {
+        BulkType::from_categories(self.file_type(), self.categories_indexes.keys().map(String::as_str))
+    }
+
     pub fn messages_count(self) -> HashMap<String, usize> {
         let mut messages_count = HashMap::new();
         for (category_name, indexes) in self.categories_indexes {
@@ -119,6 +292,7 @@ impl Iso8583File {
     /// Searches for a set of iso8583 keys and values in order to create a cloned structure
     /// containing only the searched fields.
     /// This process is memory intensive, due to the imutable nature of this method
+    #[deprecated(since = "2.0.0", note = "please use `Iso8583File::filter` with a `query::Query` instead")]
     pub fn search(self, search: HashMap<String, Vec<String>>) -> Iso8583File {
         let mut search_messages_result: Vec<Message> = vec![];
 
@@ -139,6 +313,8 @@ impl Iso8583File {
         let mut new_iso8583_files = Iso8583File {
             messages: search_messages_result,
             categories_indexes: HashMap::new(),
+            value_indexes: HashMap::new(),
+            lossless: None,
         };
 
         new_iso8583_files.assign_messages_categories().expect("Unable to assign categories messages");
@@ -160,16 +336,19 @@ impl Iso8583File {
     }
 }
 
-pub fn read_and_deblock_file<'a>(file_name: &str) -> Result<Vec<u8>> {
-    let file = file_utils::read_file(file_name);
+pub fn read_and_deblock_file(file_name: &str) -> Result<Vec<u8>> {
+    let file = file_utils::read_file(file_name)?;
     let file_contents_base64 = file_utils::deblock_and_remove_rdw_from(file)?;
     Ok(file_contents_base64)
 }
 
 pub fn parse_file(payload: Vec<u8>) -> Result<Iso8583File> {
-    //checks if file has rdw at head and blocks at tail
+    parse_file_with_specs(payload, &iso_specs::IsoSpecs::new())
+}
 
-    let handle = iso_specs::IsoSpecs::new();
+/// Parses a file whose messages follow the given specs, e.g a different bitmap layout
+pub fn parse_file_with_specs(payload: Vec<u8>, handle: &iso_specs::IsoSpecs) -> Result<Iso8583File> {
+    //checks if file has rdw at head and blocks at tail
 
     let mut current_vec_index: usize = 0;
     let mut messages: Vec<Message> = vec![];
@@ -177,57 +356,133 @@ pub fn parse_file(payload: Vec<u8>) -> Result<Iso8583File> {
     let clean_payload = file_utils::deblock_and_remove_rdw_from(payload)?;
 
     while clean_payload.len() > (current_vec_index + 2) {
-        let mut mti = "".to_owned();
-        let mut primary_bitmap: [u8; 8] = Default::default();
-        let mut data_elements: HashMap<String, iso_field::IPMValue> = HashMap::new();
-        let mut pds: HashMap<String, String> = HashMap::new();
-        let iso_msg = iso_msg::IsoMsg::new(&handle, &clean_payload[current_vec_index..]);
-        for field in iso_msg.present_fields() {
-            let value = field.iso_field_value(&clean_payload[current_vec_index..]);
-            let field_id = field.iso_field_label_id.clone();
-            let ipm_value = field.get_ipm_value(&clean_payload[current_vec_index..])?;
-
-            // this is an additional security to avoid a stack level too deep or endless-loops
-            if data_elements.contains_key(&field_id) {
-                return Err(eyre!("duplicated field should not exist on iso8583 message",));
-            }
+        let (message, message_length) = parse_message_at(&clean_payload[current_vec_index..], handle)?;
 
-            // Parse PDSs
-            // FIXME: for now pds are only implemented for de48
-            if field_id == "048" {
-                pds = match pds::get_pds_values(&value)? {
-                    Some(v) => v,
-                    None => pds,
-                }
-            }
+        messages.append(&mut vec![message]);
 
-            // Parse MTI, bitmaps and DEs
-            if field_id == "mti" {
-                mti = ipm_value.get_string();
-            } else if field_id == "bitmaps" {
-                let (primary_bitmap_slice, secondary_bitmap_slice) = value.split_at(8);
+        current_vec_index += message_length;
+    }
+    let iso8583_file = Iso8583File::new(messages)?;
 
-                primary_bitmap = primary_bitmap_slice.try_into()?;
+    Ok(iso8583_file)
+}
 
-                data_elements.insert("001".to_owned(), iso_field::IPMValue::Binary(secondary_bitmap_slice.to_vec()));
-            } else {
-                data_elements.insert(field_id, ipm_value);
-            }
-        }
+/// Parses the file messages across threads, keeping their order
+///
+/// Records given by the RDWs are parsed independently, each of them being walked message by
+/// message as `parse_file` does, so a record holding more than one message gives them all. Files
+/// without RDW are split sequentially and only their conversion into owned messages runs in parallel
+#[cfg(feature = "rayon")]
+pub fn parse_file_parallel(payload: Vec<u8>) -> Result<Iso8583File> {
+    parse_file_parallel_with_specs(payload, &iso_specs::IsoSpecs::new())
+}
 
-        let message = Message {
-            category: Message::get_category(&mti, &data_elements["024"]),
-            mti,
-            primary_bitmap,
-            data_elements,
-            pds,
-        };
+#[cfg(feature = "rayon")]
+pub fn parse_file_parallel_with_specs(payload: Vec<u8>, handle: &iso_specs::IsoSpecs) -> Result<Iso8583File> {
+    use rayon::prelude::*;
+
+    let (clean_payload, records) = file_utils::deblock_into_records(payload)?;
+
+    let messages = match records {
+        Some(records) => records
+            .into_par_iter()
+            .map(|record| {
+                message_ref::MessageRefs::new(&clean_payload[record], handle)
+                    .map(|message_ref| message_ref?.to_message())
+                    .collect::<Result<Vec<Message>>>()
+            })
+            .collect::<Result<Vec<Vec<Message>>>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+        None => message_ref::MessageRefs::new(&clean_payload, handle)
+            .collect::<Result<Vec<_>>>()?
+            .par_iter()
+            .map(|message_ref| message_ref.to_message())
+            .collect::<Result<Vec<Message>>>()?,
+    };
+
+    Iso8583File::new(messages)
+}
 
-        messages.append(&mut vec![message]);
+/// Parses a file keeping the bytes of each record and the file layout, so unchanged messages
+/// are written back byte for byte with `Iso8583File::write_lossless`
+pub fn parse_file_lossless(payload: Vec<u8>) -> Result<Iso8583File> {
+    parse_file_lossless_with_specs(payload, &iso_specs::IsoSpecs::new())
+}
 
-        current_vec_index += iso_msg.length();
-    }
-    let iso8583_file = Iso8583File::new(messages)?;
+/// Same as `parse_file_lossless`, with the specs the messages follow
+pub fn parse_file_lossless_with_specs(payload: Vec<u8>, handle: &iso_specs::IsoSpecs) -> Result<Iso8583File> {
+    let layout = lossless::FileLayout::detect(&payload);
+    let (clean_payload, records) = file_utils::deblock_into_records(payload)?;
+
+    // each record may hold several messages, the bytes left after the last one (e.g a line feed)
+    // being kept along with it
+    let record_messages = |record: &[u8]| -> Result<Vec<Message>> {
+        let message_refs = message_ref::MessageRefs::new(record, handle).collect::<Result<Vec<_>>>()?;
+        let last_index = message_refs.len().saturating_sub(1);
+        let mut position = 0usize;
+
+        message_refs
+            .into_iter()
+            .enumerate()
+            .map(|(index, message_ref)| {
+                let end = match index == last_index {
+                    true => record.len(),
+                    false => position + message_ref.length(),
+                };
+                let mut raw = lossless::RawMessage::new(&message_ref, &record[position..end])?;
+                raw.continues_record = index != last_index;
+                position = end;
+
+                let mut message = message_ref.to_message()?;
+                message.raw = Some(Box::new(raw));
+                Ok(message)
+            })
+            .collect()
+    };
+
+    let messages = match records {
+        Some(records) => records
+            .into_iter()
+            .map(|record| record_messages(&clean_payload[record]))
+            .collect::<Result<Vec<Vec<Message>>>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+        None => record_messages(&clean_payload)?,
+    };
+
+    let mut iso8583_file = Iso8583File::new(messages)?;
+    iso8583_file.lossless = Some((layout, handle.clone()));
 
     Ok(iso8583_file)
 }
+
+/// Parses a single message without RDW or blocking, e.g an online message read from a socket
+///
+/// ```
+/// use iso8583::iso_specs::IsoSpecs;
+/// use strum::EnumProperty;
+///
+/// let mut payload = b"0800".to_vec();
+/// payload.extend_from_slice(&[0x82, 0x20, 0, 0, 0, 0, 0, 0]); // DE 1, 7 and 11
+/// payload.extend_from_slice(&[0x04, 0, 0, 0, 0, 0, 0, 0]); // DE 70
+/// payload.extend_from_slice(b"1018120000123456301");
+///
+/// let message = iso8583::parse_message(&payload, &IsoSpecs::iso8583_1987()).unwrap();
+///
+/// assert_eq!(message.category.get_str("name"), Some("network_management_requests"));
+/// assert_eq!(message.data_elements["070"].get_string(), "301");
+/// ```
+pub fn parse_message(payload: &[u8], handle: &iso_specs::IsoSpecs) -> Result<Message> {
+    let (message, _) = parse_message_at(payload, handle)?;
+    Ok(message)
+}
+
+/// Parses the message at the start of the payload, returning it along with its length
+fn parse_message_at(payload: &[u8], handle: &iso_specs::IsoSpecs) -> Result<(Message, usize)> {
+    let message_ref = message_ref::MessageRef::parse(payload, handle)?;
+
+    Ok((message_ref.to_message()?, message_ref.length()))
+}
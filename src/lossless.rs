@@ -0,0 +1,266 @@
+use crate::data_elements::DataElements;
+use crate::file_utils;
+use crate::iso_field::{IPMValue, IsoField};
+use crate::iso_msg::IsoMsg;
+use crate::iso_specs::IsoSpecs;
+use crate::message_ref::MessageRef;
+use crate::{writer, Iso8583File, Message};
+use eyre::{eyre, Result};
+use std::ops::Range;
+
+/// How the records were laid out on a file, so it can be written back the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileLayout {
+    /// messages one after the other, without RDW
+    Plain,
+    /// records prefixed by their RDW
+    Rdw,
+    /// RDW records with the 1014 blocking, padded with the given byte
+    Blocked { padding: u8 },
+}
+
+impl FileLayout {
+    pub fn detect(payload: &[u8]) -> FileLayout {
+        if !file_utils::has_rdw_or_block(payload) {
+            return FileLayout::Plain;
+        }
+
+        match file_utils::is_blocked(payload) {
+            true => FileLayout::Blocked {
+                padding: payload.last().copied().unwrap_or(b'@'),
+            },
+            false => FileLayout::Rdw,
+        }
+    }
+
+    /// Lays the encoded records out, e.g adding their RDWs and blocking them
+    pub fn apply(&self, records: &[Vec<u8>]) -> Vec<u8> {
+        match self {
+            FileLayout::Plain => records.concat(),
+            FileLayout::Rdw => file_utils::add_rdw_to(records),
+            FileLayout::Blocked { padding } => file_utils::block_with(file_utils::add_rdw_to(records), *padding),
+        }
+    }
+}
+
+/// A DE as it was read: its bytes on the record, length prefix included, and the value decoded from them
+#[derive(Debug, Clone)]
+struct RawField {
+    number: usize,
+    range: Range<usize>,
+    value: IPMValue,
+}
+
+/// The record a message was parsed from, kept by `parse_file_lossless`
+///
+/// Encoding the message reuses the bytes of its MTI, bitmaps and DEs as long as their values
+/// weren't changed, so numbers keep their leading zeros and bytes after the last DE are kept as
+/// they are
+#[derive(Debug, Clone)]
+pub struct RawMessage {
+    /// the record, without its RDW
+    pub bytes: Vec<u8>,
+    mti: String,
+    mti_end: usize,
+    bitmaps_end: usize,
+    fields_end: usize,
+    fields: Vec<RawField>,
+    /// the next message was read from the same record, so both are written back on a single one
+    pub(crate) continues_record: bool,
+}
+
+impl RawMessage {
+    /// Keeps the record of a parsed message, which may go beyond the end of the message
+    pub fn new(message_ref: &MessageRef, record: &[u8]) -> Result<RawMessage> {
+        let bitmaps = &message_ref.fields[1];
+
+        let fields = message_ref
+            .fields
+            .iter()
+            .skip(2)
+            .map(|field| {
+                Ok(RawField {
+                    number: field.number,
+                    range: field.position..field.position + field.encoded_size(),
+                    value: field.to_ipm_value()?,
+                })
+            })
+            .collect::<Result<Vec<RawField>>>()?;
+
+        Ok(RawMessage {
+            bytes: record.to_vec(),
+            mti: message_ref.mti()?.into_owned(),
+            mti_end: bitmaps.position,
+            bitmaps_end: bitmaps.position + bitmaps.encoded_size(),
+            fields_end: message_ref.length(),
+            fields,
+            continues_record: false,
+        })
+    }
+
+    fn raw_field(&self, field_number: usize) -> Option<&RawField> {
+        self.fields
+            .binary_search_by_key(&field_number, |field| field.number)
+            .ok()
+            .map(|index| &self.fields[index])
+    }
+
+    /// Content length of a DE as read from the record (e.g with the leading zeros of numbers), as
+    /// long as its value is still the one read
+    pub(crate) fn content_length(&self, iso_field: &IsoField, field_number: usize, value: &IPMValue) -> Option<usize> {
+        let field = self.raw_field(field_number).filter(|field| field.value == *value)?;
+        IsoMsg::get_content_length(iso_field, &self.bytes[field.range.clone()])
+            .ok()
+            .map(|(content_length, _)| content_length)
+    }
+
+    /// Encodes the message, only re-encoding the parts that differ from the record
+    pub fn encode(&self, specs: &IsoSpecs, message: &Message) -> Result<Vec<u8>> {
+        let data_elements: Vec<(usize, &IPMValue)> = message
+            .data_elements
+            .iter()
+            .filter(|(field_number, _)| *field_number != 1 && !(*field_number == 65 && specs.bitmap_layout.tertiary))
+            .collect();
+
+        let same_fields = data_elements.len() == self.fields.len()
+            && data_elements
+                .iter()
+                .zip(self.fields.iter())
+                .all(|((field_number, _), field)| *field_number == field.number);
+
+        let unchanged = same_fields
+            && message.mti == self.mti
+            && self
+                .fields
+                .iter()
+                .zip(data_elements.iter())
+                .all(|(field, (_, value))| field.value == **value);
+
+        if unchanged {
+            return Ok(self.bytes.clone());
+        }
+
+        let mut encoded = match message.mti == self.mti {
+            true => self.bytes[..self.mti_end].to_vec(),
+            false => writer::encode_field(&specs.specs[0], &IPMValue::String(message.mti.clone()))?,
+        };
+
+        match same_fields {
+            true => encoded.extend_from_slice(&self.bytes[self.mti_end..self.bitmaps_end]),
+            false => {
+                let bitmap = writer::compute_bitmap_for(specs, message.data_elements.numbers())?;
+                encoded.append(&mut specs.bitmap_layout.encode(&bitmap));
+            }
+        }
+
+        for (field_number, value) in data_elements {
+            match self.raw_field(field_number).filter(|field| field.value == *value) {
+                Some(field) => encoded.extend_from_slice(&self.bytes[field.range.clone()]),
+                None => {
+                    let field_id = DataElements::label_id(field_number);
+                    let iso_field = specs
+                        .field(&field_id)
+                        .ok_or_else(|| eyre!("no spec found for data element {:?}", field_id))?;
+                    encoded.append(&mut writer::encode_field(iso_field, value)?);
+                }
+            }
+        }
+
+        encoded.extend_from_slice(&self.bytes[self.fields_end..]);
+
+        Ok(encoded)
+    }
+}
+
+impl Iso8583File {
+    /// How the records were laid out, only known on files parsed with `parse_file_lossless`
+    pub fn layout(&self) -> Option<FileLayout> {
+        self.lossless.as_ref().map(|(layout, _)| *layout)
+    }
+
+    /// Writes the file back with the layout and the specs it was parsed with, see `parse_file_lossless`
+    ///
+    /// Messages read from the same record are written back on a single record
+    pub fn write_lossless(&self) -> Result<Vec<u8>> {
+        let (layout, specs) = self
+            .lossless
+            .as_ref()
+            .ok_or_else(|| eyre!("the file layout is only known on files parsed with parse_file_lossless"))?;
+
+        let mut records: Vec<Vec<u8>> = vec![];
+        let mut continues_record = false;
+
+        for message in &self.messages {
+            let mut encoded = writer::encode_message(specs, message)?;
+            match records.last_mut() {
+                Some(record) if continues_record => record.append(&mut encoded),
+                _ => records.push(encoded),
+            }
+            continues_record = message.raw.as_ref().map_or(false, |raw| raw.continues_record);
+        }
+
+        Ok(layout.apply(&records))
+    }
+}
+
+#[test]
+fn test_lossless_encoding_keeps_unchanged_bytes() {
+    use crate::builder::MessageBuilder;
+
+    let specs = IsoSpecs::new();
+    let message = MessageBuilder::new(&specs)
+        .mti("1240")
+        .de(24, "200")
+        .de(48, "0158003DMC0023003POI")
+        .de(71, "1")
+        .de(93, "23379")
+        .build()
+        .unwrap();
+
+    // DE 93 with a leading zero, which is lost once decoded as a number
+    let encoded = writer::encode_message(&specs, &message).unwrap();
+    let position = encoded.windows(7).position(|bytes| bytes == b"0523379").unwrap();
+    let record = [&encoded[..position], b"06023379".as_slice(), &encoded[position + 7..]].concat();
+
+    let payload = file_utils::add_rdw_to(std::slice::from_ref(&record));
+    let mut iso8583_file = crate::parse_file_lossless(payload.clone()).unwrap();
+    assert_eq!(iso8583_file.layout(), Some(FileLayout::Rdw));
+    assert_eq!(iso8583_file.write_lossless().unwrap(), payload);
+
+    let message = &mut iso8583_file.messages[0];
+    assert_eq!(message.pds.keys().collect::<Vec<_>>(), vec!["0158", "0023"]);
+    message.set_pds("0023", "ATM").unwrap();
+    assert_eq!(message.data_elements[48].get_string(), "0158003DMC0023003ATM");
+
+    let written = writer::encode_message(&specs, message).unwrap();
+    assert_eq!(written.len(), record.len());
+    assert!(written.windows(8).any(|bytes| bytes == b"06023379"));
+    assert!(crate::parse_file(payload).unwrap().write_lossless().is_err());
+}
+
+#[test]
+fn test_lossless_encoding_with_the_parsing_specs() {
+    use crate::builder::MessageBuilder;
+
+    let specs = IsoSpecs::iso8583_1987();
+    let message = MessageBuilder::new(&specs)
+        .mti("0100")
+        .de(3, "000000")
+        .de(4, "1500")
+        .de(11, "123456")
+        .build()
+        .unwrap();
+
+    let payload = file_utils::add_rdw_to(&[writer::encode_message(&specs, &message).unwrap()]);
+    let mut iso8583_file = crate::parse_file_lossless_with_specs(payload.clone(), &specs).unwrap();
+    assert_eq!(iso8583_file.write_lossless().unwrap(), payload);
+
+    // DE 43 has 40 fixed characters on the 1987 specs, it's a LLVAR on the IPM ones
+    let card_acceptor = format!("{:40}", "MERCHANT SAO PAULO BR");
+    iso8583_file.messages[0].data_elements.set_de(4, IPMValue::u64(2500));
+    iso8583_file.messages[0].data_elements.set_de(43, IPMValue::String(card_acceptor.clone()));
+    let written = iso8583_file.write_lossless().unwrap();
+    let parsed = crate::parse_file_lossless_with_specs(written, &specs).unwrap();
+    assert_eq!(parsed.messages[0].data_elements[4].get_string(), "2500");
+    assert_eq!(parsed.messages[0].data_elements[43].get_string(), card_acceptor);
+}
@@ -0,0 +1,307 @@
+use crate::data_elements::DataElements;
+use crate::iso_field::{IPMValue, IsoField};
+use crate::iso_msg::{Bitmap, IsoMsg};
+use crate::iso_specs::{Category, IsoSpecs};
+use crate::{encoding, pds, Message};
+use eyre::{eyre, Result};
+use std::borrow::Cow;
+use std::fmt;
+
+/// A field of a `MessageRef`, borrowing its content from the input buffer
+#[derive(Debug, Clone, Copy)]
+pub struct FieldRef<'a> {
+    /// position of the field on the specs, 0 for the MTI, 1 for the bitmaps and the DE number otherwise
+    pub number: usize,
+    pub spec: &'a IsoField,
+    /// where the field starts on the message, length prefix included
+    pub position: usize,
+    /// content without its length prefix, still in the field encoding
+    pub raw: &'a [u8],
+    /// in characters, e.g the number of digits for BCD
+    pub content_length: usize,
+}
+
+impl<'a> FieldRef<'a> {
+    pub fn label_id(&self) -> &'a str {
+        &self.spec.label_id
+    }
+
+    /// Bytes taken by the field on the message, length prefix included
+    pub fn encoded_size(&self) -> usize {
+        self.spec.length_prefix_size() + self.raw.len()
+    }
+
+    /// Content converted to ASCII, only allocating for EBCDIC and BCD fields
+    pub fn decoded(&self) -> Result<Cow<'a, [u8]>> {
+        match self.spec.content_encoding.is_ascii_compatible() {
+            true => Ok(Cow::Borrowed(self.raw)),
+            false => encoding::decode_content(self.spec.content_encoding, self.raw, self.content_length).map(Cow::Owned),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<Cow<'a, str>> {
+        match self.decoded()? {
+            Cow::Borrowed(bytes) => Ok(String::from_utf8_lossy(bytes)),
+            Cow::Owned(bytes) => Ok(Cow::Owned(String::from_utf8_lossy(&bytes).into_owned())),
+        }
+    }
+
+    pub fn to_ipm_value(&self) -> Result<IPMValue> {
+        IPMValue::from_content(&self.spec.char_type, self.spec.length_prefix_digits() > 0, self.decoded()?.into_owned())
+    }
+}
+
+/// A message borrowing its fields from the parsed buffer, with an opt-in conversion into an owned `Message`
+///
+/// Nothing is copied while parsing, fields are only decoded when read
+#[derive(Clone)]
+pub struct MessageRef<'a> {
+    pub payload: &'a [u8],
+    pub specs: &'a IsoSpecs,
+    pub bitmap: Bitmap,
+    /// present fields, sorted by their number
+    pub fields: Vec<FieldRef<'a>>,
+}
+
+impl<'a> MessageRef<'a> {
+    /// Parses the message at the start of the payload, which may be followed by other messages
+    pub fn parse(payload: &'a [u8], specs: &'a IsoSpecs) -> Result<MessageRef<'a>> {
+        let mti_spec = &specs.specs[0];
+        let mti_size = encoding::encoded_size(mti_spec.content_encoding, mti_spec.length);
+        let mti = payload
+            .get(0..mti_size)
+            .ok_or_else(|| eyre!("message too short for the mti: {} bytes", payload.len()))?;
+
+        let (bitmap, bitmaps_size) = specs.bitmap_layout.decode(&payload[mti_size..]).map_err(|e| eyre!(e))?;
+
+        let mut fields = vec![
+            FieldRef {
+                number: 0,
+                spec: mti_spec,
+                position: 0,
+                raw: mti,
+                content_length: mti_spec.length,
+            },
+            FieldRef {
+                number: 1,
+                spec: &specs.specs[1],
+                position: mti_size,
+                raw: &payload[mti_size..mti_size + bitmaps_size],
+                content_length: bitmaps_size,
+            },
+        ];
+        let mut position = mti_size + bitmaps_size;
+
+        for (number, spec) in specs.specs.iter().enumerate().skip(2) {
+            let is_the_tertiary_bitmap = specs.bitmap_layout.tertiary && number == 65;
+            if is_the_tertiary_bitmap || !bitmap.get(number - 1).unwrap_or(false) {
+                continue;
+            }
+
+            let (field, field_size) = field_at(number, spec, payload, position)?;
+            position += field_size;
+            fields.push(field);
+        }
+
+        Ok(MessageRef {
+            payload: &payload[..position],
+            specs,
+            bitmap,
+            fields,
+        })
+    }
+
+    /// Encoded size of the message, i.e where the next message starts
+    pub fn length(&self) -> usize {
+        self.payload.len()
+    }
+
+    pub fn mti(&self) -> Result<Cow<'a, str>> {
+        self.fields[0].as_str()
+    }
+
+    /// Finds a field by its number, e.g `de(24)` for the function code
+    pub fn de(&self, number: usize) -> Option<&FieldRef<'a>> {
+        self.fields
+            .binary_search_by_key(&number, |field| field.number)
+            .ok()
+            .map(|index| &self.fields[index])
+    }
+
+    /// Finds a field by its label id, e.g `"024"`
+    pub fn field(&self, label_id: &str) -> Option<&FieldRef<'a>> {
+        self.fields.iter().find(|field| field.label_id() == label_id)
+    }
+
+    pub fn category(&self) -> Result<Category> {
+        let function_code = self.field("024").map(|field| field.to_ipm_value()).transpose()?;
+        Ok(Message::get_category(self.specs.profile, &self.mti()?, function_code.as_ref()))
+    }
+
+    /// Looks up a single PDS on the PDS data elements, borrowing it when they are ASCII
+    pub fn pds(&self, pds_id: &str) -> Result<Option<Cow<'a, str>>> {
+        for label_id in self.specs.pds_data_elements.iter() {
+            let field = match self.field(label_id) {
+                Some(field) => field,
+                None => continue,
+            };
+
+            let pds_value = match field.decoded()? {
+                Cow::Borrowed(bytes) => pds::find_pds(str_from(bytes)?, pds_id).map(Cow::Borrowed),
+                Cow::Owned(bytes) => pds::find_pds(str_from(&bytes)?, pds_id).map(|value| Cow::Owned(value.to_string())),
+            };
+
+            if pds_value.is_some() {
+                return Ok(pds_value);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Copies the message into an owned `Message`
+    pub fn to_message(&self) -> Result<Message> {
+        let mut data_elements = DataElements::new();
+        let mut pds = pds::PdsCollection::new();
+
+        let (primary_bitmap, secondary, tertiary) = self.specs.bitmap_layout.split(&self.bitmap);
+
+        // secondary and tertiary bitmaps are kept as DE 1 and DE 65
+        if let Some(secondary) = secondary {
+            data_elements.set_de(1, IPMValue::Binary(secondary.to_vec()));
+        }
+        if let Some(tertiary) = tertiary {
+            data_elements.set_de(65, IPMValue::Binary(tertiary.to_vec()));
+        }
+
+        for field in self.fields.iter().skip(2) {
+            // Parse PDSs
+            if self.specs.pds_data_elements.contains(&field.label_id()) {
+                pds.read(field.number, str_from(&field.decoded()?)?);
+            }
+
+            data_elements.set_de(field.number, field.to_ipm_value()?);
+        }
+
+        let mti = self.mti()?.into_owned();
+
+        Ok(Message {
+            category: Message::get_category(self.specs.profile, &mti, data_elements.de(24)),
+            mti,
+            primary_bitmap,
+            data_elements,
+            pds,
+            raw: None,
+        })
+    }
+}
+
+impl fmt::Debug for MessageRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MessageRef")
+            .field("payload", &self.payload)
+            .field("fields", &self.fields)
+            .finish()
+    }
+}
+
+impl TryFrom<&MessageRef<'_>> for Message {
+    type Error = eyre::Report;
+
+    fn try_from(message_ref: &MessageRef<'_>) -> Result<Message> {
+        message_ref.to_message()
+    }
+}
+
+/// Reads the field at `position`, returning it along with its encoded size, length prefix included
+fn field_at<'a>(number: usize, spec: &'a IsoField, payload: &'a [u8], position: usize) -> Result<(FieldRef<'a>, usize)> {
+    let out_of_bounds = || eyre!("DE {} goes beyond the message end at position {}", number, position);
+
+    let field_payload = payload.get(position..).ok_or_else(out_of_bounds)?;
+    let (content_length, prefix_size) = IsoMsg::get_content_length(spec, field_payload)?;
+
+    let start = position + prefix_size;
+    let raw = payload
+        .get(start..start + encoding::encoded_size(spec.content_encoding, content_length))
+        .ok_or_else(out_of_bounds)?;
+
+    let field_size = prefix_size + raw.len();
+    let field = FieldRef {
+        number,
+        spec,
+        position,
+        raw,
+        content_length,
+    };
+
+    Ok((field, field_size))
+}
+
+fn str_from(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|_| eyre!("unable to get pds values for {:?}", bytes))
+}
+
+/// Iterates over the messages of a payload without RDW or blocking, borrowing each of them
+pub struct MessageRefs<'a> {
+    payload: &'a [u8],
+    specs: &'a IsoSpecs,
+    position: usize,
+}
+
+impl<'a> MessageRefs<'a> {
+    pub fn new(payload: &'a [u8], specs: &'a IsoSpecs) -> MessageRefs<'a> {
+        MessageRefs {
+            payload,
+            specs,
+            position: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for MessageRefs<'a> {
+    type Item = Result<MessageRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.payload.len() <= self.position + 2 {
+            return None;
+        }
+
+        match MessageRef::parse(&self.payload[self.position..], self.specs) {
+            Ok(message_ref) => {
+                self.position += message_ref.length();
+                Some(Ok(message_ref))
+            }
+            Err(e) => {
+                self.position = self.payload.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_message_ref_borrows_fields() {
+    let specs = IsoSpecs::iso8583_1987();
+
+    let mut payload = b"0800".to_vec();
+    payload.extend_from_slice(&[0x82, 0x20, 0, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(&[0x04, 0, 0, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(b"1018120000123456301");
+    payload.extend_from_slice(b"0810");
+
+    let message_ref = MessageRef::parse(&payload, &specs).unwrap();
+    assert_eq!(message_ref.length(), payload.len() - 4);
+    assert_eq!(message_ref.mti().unwrap(), "0800");
+
+    let stan = message_ref.de(11).unwrap();
+    assert_eq!(stan.raw, b"123456");
+    assert!(matches!(stan.as_str().unwrap(), Cow::Borrowed("123456")));
+    assert_eq!(message_ref.field("070").unwrap().number, 70);
+    assert!(message_ref.de(2).is_none());
+
+    let message = message_ref.to_message().unwrap();
+    assert_eq!(message.data_elements.de(11).unwrap().get_string(), "123456");
+    assert!(message.data_elements.contains_de(1));
+
+    assert!(MessageRef::parse(&payload[..20], &specs).is_err());
+}
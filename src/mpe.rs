@@ -0,0 +1,206 @@
+use crate::Message;
+use eyre::{eyre, Result};
+use std::collections::HashMap;
+
+const ACCOUNT_RANGE_TABLE_ID: &str = "IP0040T1";
+const RANGE_SIZE: usize = 19;
+
+/// A field of a MPE record, positioned as on the extract (1-based)
+struct RecordField {
+    position: usize,
+    length: usize,
+}
+
+const TABLE_ID: RecordField = RecordField { position: 13, length: 8 };
+const ACTIVE_INACTIVE_CODE: RecordField = RecordField { position: 12, length: 1 };
+
+// IP0040T1 - Issuer Account Range
+const LOW_RANGE: RecordField = RecordField { position: 21, length: 19 };
+const GCMS_PRODUCT_ID: RecordField = RecordField { position: 40, length: 3 };
+const HIGH_RANGE: RecordField = RecordField { position: 43, length: 19 };
+const CARD_PROGRAM_IDENTIFIER: RecordField = RecordField { position: 62, length: 3 };
+const ISSUER_ICA: RecordField = RecordField { position: 67, length: 11 };
+const COUNTRY_CODE: RecordField = RecordField { position: 86, length: 3 };
+const REGION: RecordField = RecordField { position: 92, length: 1 };
+const LICENSED_PRODUCT_ID: RecordField = RecordField { position: 93, length: 3 };
+
+fn record_str<'a>(record: &'a str, record_field: &RecordField) -> Result<&'a str> {
+    record
+        .get(record_field.position - 1..record_field.position - 1 + record_field.length)
+        .ok_or_else(|| eyre!("mpe record too short for position {}: {:?}", record_field.position, record))
+}
+
+/// An entry of the issuer account range table (IP0040T1)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountRange {
+    pub low_range: String,
+    pub high_range: String,
+    /// e.g `MCC` (Mastercard credit), `DMC` (Debit Mastercard), `MSI` (Maestro)
+    pub card_program_identifier: String,
+    pub gcms_product_id: String,
+    pub licensed_product_id: String,
+    pub issuer_ica: String,
+    /// ISO alpha country code of the issuer, e.g `BRA`
+    pub country_code: String,
+    pub region: String,
+}
+
+impl AccountRange {
+    fn from_record(record: &str) -> Result<AccountRange> {
+        let field = |record_field: &RecordField| record_str(record, record_field).map(|value| value.trim().to_string());
+
+        let account_range = AccountRange {
+            low_range: field(&LOW_RANGE)?,
+            high_range: field(&HIGH_RANGE)?,
+            card_program_identifier: field(&CARD_PROGRAM_IDENTIFIER)?,
+            gcms_product_id: field(&GCMS_PRODUCT_ID)?,
+            licensed_product_id: field(&LICENSED_PRODUCT_ID)?,
+            issuer_ica: field(&ISSUER_ICA)?,
+            country_code: field(&COUNTRY_CODE)?,
+            region: field(&REGION)?,
+        };
+
+        let is_a_range = |range: &str| range.len() == RANGE_SIZE && range.chars().all(|c| c.is_ascii_digit());
+        if !is_a_range(&account_range.low_range) || !is_a_range(&account_range.high_range) {
+            return Err(eyre!("invalid account range on mpe record {:?}", record));
+        }
+
+        Ok(account_range)
+    }
+
+    pub fn brand(&self) -> &'static str {
+        match self.card_program_identifier.as_str() {
+            "MCC" | "DMC" => "Mastercard",
+            "MSI" => "Maestro",
+            "CIR" => "Cirrus",
+            _ => "unknown",
+        }
+    }
+
+    pub fn region_name(&self) -> &'static str {
+        match self.region.as_str() {
+            "1" => "United States",
+            "A" => "Canada",
+            "B" => "Latin America and the Caribbean",
+            "C" => "Asia/Pacific",
+            "D" => "Europe",
+            "E" => "South Asia/Middle East/Africa",
+            _ => "unknown",
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.low_range.as_str() <= key && key <= self.high_range.as_str()
+    }
+}
+
+/// Lookup tables built from a Mastercard Parameter Extract (MPE)
+///
+/// Account ranges (IP0040T1) are decoded, other tables (e.g IP0000T1) are kept as raw records
+#[derive(Debug, Clone, Default)]
+pub struct MpeTables {
+    /// active account ranges, sorted by their low range
+    pub account_ranges: Vec<AccountRange>,
+    pub raw_tables: HashMap<String, Vec<String>>,
+}
+
+impl MpeTables {
+    /// Loads the fixed length records of the extract, one per line, skipping inactive entries
+    pub fn load(payload: &[u8]) -> Result<MpeTables> {
+        let content = std::str::from_utf8(payload).map_err(|_| eyre!("mpe extract should be ascii"))?;
+        let mut tables = MpeTables::default();
+
+        for record in content.lines().filter(|line| !line.trim().is_empty()) {
+            let table_id = record_str(record, &TABLE_ID)?.trim();
+
+            if table_id != ACCOUNT_RANGE_TABLE_ID {
+                tables
+                    .raw_tables
+                    .entry(table_id.to_string())
+                    .or_default()
+                    .push(record.to_string());
+            } else if record_str(record, &ACTIVE_INACTIVE_CODE)? != "I" {
+                tables.account_ranges.push(AccountRange::from_record(record)?);
+            }
+        }
+
+        tables
+            .account_ranges
+            .sort_by(|a, b| a.low_range.cmp(&b.low_range));
+
+        Ok(tables)
+    }
+
+    pub fn load_file(file_name: &str) -> Result<MpeTables> {
+        MpeTables::load(&std::fs::read(file_name)?)
+    }
+
+    /// Finds the account range of a PAN, which may be masked (e.g `549999******9999`), as long as
+    /// its first digits are enough to tell the range apart
+    pub fn find_account_range(&self, pan: &str) -> Option<&AccountRange> {
+        let digits: String = pan.chars().take_while(|c| c.is_ascii_digit()).take(RANGE_SIZE).collect();
+        let key = format!("{:0<w$}", digits, w = RANGE_SIZE);
+
+        let candidates = self.account_ranges.partition_point(|range| range.low_range <= key);
+        self.account_ranges[..candidates]
+            .iter()
+            .rev()
+            .find(|range| range.contains(&key))
+    }
+}
+
+impl Message {
+    /// Enriches the message with the issuer account range of its PAN (DE 2), returns `None`
+    /// when the message has no PAN or the PAN isn't on the tables
+    pub fn account_range<'a>(&self, tables: &'a MpeTables) -> Result<Option<&'a AccountRange>> {
+        match self.pan()? {
+            Some(pan) => Ok(tables.find_account_range(&pan.value)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_account_range_record(active: &str, low: &str, high: &str, card_program: &str, ica: &str, country: &str) -> String {
+    format!(
+        "{:11}{:1}{:8}{:19}{:3}{:19}{:3}{:2}{:11}{:1}{:7}{:3}{:3}{:1}{:3}",
+        "22001000000", active, "IP0040T1", low, "MCC", high, card_program, "01", ica, "1", "0000001", country, "076", "B",
+        "MCC"
+    )
+}
+
+#[test]
+fn test_load_and_find_account_ranges() {
+    let extract = [
+        "22001000000AIP0000T1 SOME OTHER TABLE".to_string(),
+        test_account_range_record("A", "5499990000000000000", "5499999999999999999", "MCC", "00000012345", "BRA"),
+        test_account_range_record("A", "5067000000000000000", "5067999999999999999", "MSI", "00000067890", "BRA"),
+        test_account_range_record("I", "5100000000000000000", "5199999999999999999", "MCC", "00000000001", "USA"),
+    ]
+    .join("\n");
+
+    let tables = MpeTables::load(extract.as_bytes()).unwrap();
+    assert_eq!(tables.account_ranges.len(), 2);
+    assert_eq!(tables.raw_tables["IP0000T1"].len(), 1);
+
+    let account_range = tables.find_account_range("5499999999999998").unwrap();
+    assert_eq!(account_range.issuer_ica, "00000012345");
+    assert_eq!(account_range.country_code, "BRA");
+    assert_eq!(account_range.brand(), "Mastercard");
+    assert_eq!(account_range.region_name(), "Latin America and the Caribbean");
+
+    assert_eq!(tables.find_account_range("506799******1234").unwrap().brand(), "Maestro");
+    assert!(tables.find_account_range("5100000000000000").is_none());
+    assert!(tables.find_account_range("4111111111111111").is_none());
+
+    assert!(MpeTables::load(b"22001000000AIP0040T1 54999").is_err());
+
+    let specs = crate::iso_specs::IsoSpecs::new();
+    let message = crate::builder::MessageBuilder::new(&specs)
+        .mti("1240")
+        .de(2, "5499999999999998")
+        .de(24, "200")
+        .build()
+        .unwrap();
+    assert_eq!(message.account_range(&tables).unwrap().unwrap().issuer_ica, "00000012345");
+}
@@ -1,44 +1,452 @@
+use crate::iso_specs::Profile;
 use eyre::{eyre, Result};
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
 
 const PDS_LEN_SIZE: usize = 3;
 const PDS_ID_SIZE: usize = 4;
+/// Longest PDS value, its length being written on 3 digits
+pub const PDS_MAX_LEN: usize = 999;
+/// Longest content of the data elements carrying PDS, DE 48, 62, 123, 124 and 125 being LLLVAR
+pub const PDS_DATA_ELEMENT_MAX_LEN: usize = 999;
+
+/// PDS names as on the IPM clearing formats
+const MASTERCARD_PDS_NAMES: &[(&str, &str)] = &[
+    ("0002", "GCMS Product Identifier"),
+    ("0003", "Licensed Product Identifier"),
+    ("0005", "Message Error Indicator"),
+    ("0023", "Terminal Type"),
+    ("0025", "Message Reversal Indicator"),
+    ("0026", "File Reversal Indicator"),
+    ("0043", "Program Registration ID"),
+    ("0052", "Electronic Commerce Security Level Indicator"),
+    ("0057", "Transaction Category Indicator"),
+    ("0105", "File ID"),
+    ("0110", "Transmission ID"),
+    ("0122", "Processing Mode"),
+    ("0137", "Fee Collection Control Number"),
+    ("0138", "Source Message Number ID"),
+    ("0146", "Amounts, Transaction Fee"),
+    ("0147", "Extended Precision Amounts"),
+    ("0148", "Currency Exponents"),
+    ("0149", "Currency Codes, Amounts, Original"),
+    ("0158", "Business Activity"),
+    ("0159", "Settlement Data"),
+    ("0164", "Currency Cross Rates"),
+    ("0165", "Settlement Indicator"),
+    ("0170", "Card Acceptor Inquiry Information"),
+    ("0177", "Cross-border"),
+    ("0181", "Installment Payment Data"),
+    ("0191", "Originating Message Format"),
+    ("0198", "Device Type"),
+    ("0207", "Wallet Identifier"),
+    ("0262", "Documentation Indicator"),
+    ("0264", "Original Retrieval Reason for Request"),
+    ("0280", "Source File ID"),
+    ("0300", "Reconciled, File"),
+    ("0301", "File Amount, Checksum"),
+    ("0302", "Reconciled, Member Activity"),
+    ("0306", "File Message Counts"),
+    ("0358", "Reconciled, Card Program Identifier"),
+    ("0359", "Reconciled, Settlement Service"),
+    ("0372", "Reconciled, Transaction Function"),
+    ("0374", "Reconciled, Processing Code"),
+    ("0378", "Original/Reversal Totals Indicator"),
+    ("0380", "Debits, Transaction Amount in Transaction Currency"),
+    ("0381", "Credits, Transaction Amount in Transaction Currency"),
+    ("0384", "Amount, Net Transaction in Transaction Currency"),
+    ("0390", "Debits, Transaction Amount in Reconciliation Currency"),
+    ("0391", "Credits, Transaction Amount in Reconciliation Currency"),
+    ("0392", "Debits, Fee Amount in Reconciliation Currency"),
+    ("0393", "Credits, Fee Amount in Reconciliation Currency"),
+    ("0394", "Amount, Net Transaction in Reconciliation Currency"),
+    ("0395", "Amount, Net Fee in Reconciliation Currency"),
+    ("0396", "Amount, Net Total in Reconciliation Currency"),
+    ("0400", "Debits, Transaction Number"),
+    ("0401", "Credits, Transaction Number"),
+    ("0402", "Total, Transaction Number"),
+];
+
+/// PDS names that differ from the IPM ones, or only show up, on Elo and Brazilian domestic files
+const ELO_PDS_NAMES: &[(&str, &str)] = &[
+    ("0181", "Installment Payment Data (Parcelado)"),
+    ("0182", "Installment Number (Parcela)"),
+    ("0183", "Installment Anticipation Indicator"),
+    ("0195", "Merchant Tax ID (CNPJ/CPF)"),
+];
+
+/// Name of a PDS on the given profile, falling back to the IPM names
+pub fn pds_name(profile: Profile, pds_id: &str) -> Option<&'static str> {
+    let profile_names = match profile {
+        Profile::Mastercard => &[],
+        Profile::Elo => ELO_PDS_NAMES,
+    };
+
+    profile_names
+        .iter()
+        .chain(MASTERCARD_PDS_NAMES.iter())
+        .find(|(id, _)| *id == pds_id)
+        .map(|(_, name)| *name)
+}
+
+/// A PDS along with where it was read from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PdsEntry {
+    pub id: String,
+    pub value: String,
+    /// data element carrying the PDS, DE 48 for the ones added after parsing
+    pub data_element: usize,
+    /// where the PDS id starts on the data element content, `None` for the ones added after parsing
+    pub offset: Option<usize>,
+}
+
+impl PdsEntry {
+    /// The PDS as carried by its data element, `IIIILLLV`
+    pub fn encode(&self) -> String {
+        format!("{}{:03}{}", self.id, self.value.len(), self.value)
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        PDS_ID_SIZE + PDS_LEN_SIZE + self.value.len()
+    }
+}
+
+/// Content of a data element from where no PDS could be read anymore, kept to be encoded back as is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MalformedPds {
+    pub data_element: usize,
+    pub offset: usize,
+    pub content: String,
+    pub reason: String,
+}
+
+/// PDS of a message in the order they were read, e.g from DE 48
+///
+/// Lookups are linear, messages carrying a few dozen PDS at most. Updating a PDS keeps its
+/// position, new ones are appended to the data element of the last PDS (DE 48 on an empty collection),
+/// `distribute` lays them out again once they overflow it
+#[derive(Clone, Default)]
+pub struct PdsCollection {
+    entries: Vec<PdsEntry>,
+    malformed: Vec<MalformedPds>,
+}
+
+impl PdsCollection {
+    pub fn new() -> PdsCollection {
+        PdsCollection::default()
+    }
+
+    /// Reads the PDS of a data element content
+    pub fn parse(data_element: usize, full_pds_text: &str) -> PdsCollection {
+        let mut pds = PdsCollection::new();
+        pds.read(data_element, full_pds_text);
+        pds
+    }
+
+    /// Appends the PDS of another data element content, e.g DE 62 after DE 48
+    pub fn read(&mut self, data_element: usize, full_pds_text: &str) {
+        let mut position = 0usize;
+
+        while position < full_pds_text.len() {
+            match pds_at(full_pds_text, position) {
+                Ok((pds_id, pds_value)) => {
+                    self.entries.push(PdsEntry {
+                        id: pds_id.to_string(),
+                        value: pds_value.to_string(),
+                        data_element,
+                        offset: Some(position),
+                    });
+                    position += PDS_ID_SIZE + PDS_LEN_SIZE + pds_value.len();
+                }
+                Err(reason) => {
+                    self.malformed.push(MalformedPds {
+                        data_element,
+                        offset: position,
+                        content: full_pds_text[position..].to_string(),
+                        reason,
+                    });
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn entry(&self, pds_id: &str) -> Option<&PdsEntry> {
+        self.entries.iter().find(|entry| entry.id == pds_id)
+    }
+
+    pub fn get(&self, pds_id: &str) -> Option<&String> {
+        self.entry(pds_id).map(|entry| &entry.value)
+    }
+
+    pub fn contains_key(&self, pds_id: &str) -> bool {
+        self.entry(pds_id).is_some()
+    }
+
+    /// Sets a PDS, returning its previous value
+    pub fn insert(&mut self, pds_id: &str, value: &str) -> Option<String> {
+        let (data_element, previous_value) = match self.entries.iter_mut().find(|entry| entry.id == pds_id) {
+            Some(entry) => (entry.data_element, Some(std::mem::replace(&mut entry.value, value.to_string()))),
+            None => {
+                let data_element = self.entries.last().map_or(48, |entry| entry.data_element);
+                self.entries.push(PdsEntry {
+                    id: pds_id.to_string(),
+                    value: value.to_string(),
+                    data_element,
+                    offset: None,
+                });
+                (data_element, None)
+            }
+        };
+
+        self.update_offsets(data_element);
+        previous_value
+    }
+
+    pub fn remove(&mut self, pds_id: &str) -> Option<String> {
+        let index = self.entries.iter().position(|entry| entry.id == pds_id)?;
+        let entry = self.entries.remove(index);
+        self.update_offsets(entry.data_element);
+        Some(entry.value)
+    }
+
+    /// Moves the offsets of the PDS read from a data element, and of its malformed content, after
+    /// one of its PDS changed
+    fn update_offsets(&mut self, data_element: usize) {
+        let mut position = 0usize;
+
+        for entry in self.entries.iter_mut().filter(|entry| entry.data_element == data_element) {
+            if entry.offset.is_some() {
+                entry.offset = Some(position);
+            }
+            position += entry.encoded_len();
+        }
+
+        for malformed in self.malformed.iter_mut().filter(|malformed| malformed.data_element == data_element) {
+            malformed.offset = position;
+            position += malformed.content.len();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// PDS ids and values, in order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> + '_ {
+        self.entries.iter().map(|entry| (&entry.id, &entry.value))
+    }
+
+    /// PDS ids, in order
+    pub fn keys(&self) -> impl Iterator<Item = &String> + '_ {
+        self.entries.iter().map(|entry| &entry.id)
+    }
+
+    pub fn entries(&self) -> &[PdsEntry] {
+        &self.entries
+    }
+
+    /// Contents that couldn't be read as PDS, e.g a non numeric length or a PDS longer than what's left
+    pub fn malformed(&self) -> &[MalformedPds] {
+        &self.malformed
+    }
+
+    /// Builds the content of a data element from its PDS in order, followed by its malformed content
+    pub fn encode(&self, data_element: usize) -> String {
+        let mut encoded: String = self
+            .entries
+            .iter()
+            .filter(|entry| entry.data_element == data_element)
+            .map(PdsEntry::encode)
+            .collect();
+
+        for malformed in self.malformed.iter().filter(|malformed| malformed.data_element == data_element) {
+            encoded.push_str(&malformed.content);
+        }
+
+        encoded
+    }
+
+    /// Lays the PDS out, in order, over the given data elements and their max lengths, e.g DE 48,
+    /// 62, 123, 124 and 125 of 999 characters each on IPM messages
+    ///
+    /// Each data element takes PDS until the next one doesn't fit, which then starts the next data
+    /// element: a PDS is never split. Malformed contents stay on their data element, after its PDS
+    pub fn distribute(&mut self, data_elements: &[(usize, usize)]) -> Result<()> {
+        let available = |index: usize| {
+            let (data_element, max_length) = data_elements[index];
+            let malformed_len: usize = self
+                .malformed
+                .iter()
+                .filter(|malformed| malformed.data_element == data_element)
+                .map(|malformed| malformed.content.len())
+                .sum();
+            (data_element, max_length.saturating_sub(malformed_len))
+        };
+
+        let mut layout = Vec::with_capacity(self.entries.len());
+        let mut index = 0usize;
+        let mut offset = 0usize;
+
+        for entry in self.entries.iter() {
+            if entry.value.len() > PDS_MAX_LEN {
+                return Err(eyre!("pds {} has {} characters, max is {}", entry.id, entry.value.len(), PDS_MAX_LEN));
+            }
+
+            while index < data_elements.len() && offset + entry.encoded_len() > available(index).1 {
+                index += 1;
+                offset = 0;
+            }
+
+            if index == data_elements.len() {
+                let labels: Vec<String> = data_elements.iter().map(|(data_element, _)| data_element.to_string()).collect();
+                return Err(eyre!("pds {} doesn't fit on DE {}", entry.id, labels.join(", ")));
+            }
+
+            layout.push((available(index).0, offset));
+            offset += entry.encoded_len();
+        }
+
+        for (entry, (data_element, offset)) in self.entries.iter_mut().zip(layout) {
+            entry.data_element = data_element;
+            entry.offset = Some(offset);
+        }
+
+        for malformed in self.malformed.iter_mut() {
+            malformed.offset = self
+                .entries
+                .iter()
+                .filter(|entry| entry.data_element == malformed.data_element)
+                .map(PdsEntry::encoded_len)
+                .sum();
+        }
+
+        Ok(())
+    }
+}
+
+impl Index<&str> for PdsCollection {
+    type Output = String;
+
+    fn index(&self, pds_id: &str) -> &String {
+        self.get(pds_id)
+            .unwrap_or_else(|| panic!("pds {:?} not present", pds_id))
+    }
+}
+
+/// Equal when holding the same PDS in the same order and the same malformed contents, wherever they were read
+impl PartialEq for PdsCollection {
+    fn eq(&self, other: &PdsCollection) -> bool {
+        self.iter().eq(other.iter())
+            && self
+                .malformed
+                .iter()
+                .map(|malformed| &malformed.content)
+                .eq(other.malformed.iter().map(|malformed| &malformed.content))
+    }
+}
+
+impl Eq for PdsCollection {}
+
+impl<'a> FromIterator<(&'a str, &'a str)> for PdsCollection {
+    fn from_iter<I: IntoIterator<Item = (&'a str, &'a str)>>(iter: I) -> Self {
+        let mut pds = PdsCollection::new();
+        for (pds_id, value) in iter {
+            pds.insert(pds_id, value);
+        }
+        pds
+    }
+}
+
+impl<'a, const N: usize> From<[(&'a str, &'a str); N]> for PdsCollection {
+    fn from(values: [(&'a str, &'a str); N]) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl fmt::Debug for PdsCollection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Serialized as a map keyed by PDS id, in order
+impl serde::Serialize for PdsCollection {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(self.iter())
+    }
+}
 
 /// Each pds comes with a fixed id, length and value, each are concatenated to each other
 /// so a typical pds is (IIIILLLV(V+)+) where Id is always length 4, and the Length is always 3 characters
+///
+/// Reading stops at the first malformed PDS, see `PdsCollection` to keep what follows it
 pub fn get_pds_values(additional_data: &Vec<u8>) -> Result<Option<HashMap<String, String>>> {
     if additional_data.len() < PDS_LEN_SIZE {
         return Ok(None);
     }
     if let Ok(full_pds_text) = std::str::from_utf8(additional_data) {
-        let mut position = 0usize;
-        let mut pds_values: HashMap<String, String> = HashMap::new();
-        while let Some((pds_size, pds_id, pds_value)) = pds_details(full_pds_text, position) {
-            pds_values.insert(pds_id.to_string(), pds_value.to_string());
-
-            position = position + PDS_LEN_SIZE + PDS_ID_SIZE + pds_size;
-        }
+        let pds_values = PdsCollection::parse(48, full_pds_text)
+            .iter()
+            .map(|(pds_id, value)| (pds_id.clone(), value.clone()))
+            .collect();
         Ok(Some(pds_values))
     } else {
         Err(eyre!("unable to get pds values for {:?}", &additional_data))
     }
 }
 
-fn pds_details(pds_buffer: &str, position: usize) -> Option<(usize, &str, &str)> {
-    if pds_buffer.len() > position + PDS_LEN_SIZE + PDS_ID_SIZE {
-        let size_position = position + PDS_ID_SIZE;
-        let value_position = size_position + PDS_LEN_SIZE;
-
-        match pds_buffer[size_position..value_position].parse::<usize>() {
-            Ok(i) => Some((
-                i,
-                &pds_buffer[position..size_position],
-                &pds_buffer[value_position..value_position + i],
-            )),
-            Err(_) => None,
+/// Finds a single PDS on a IIIILLLV text, without collecting the others
+pub fn find_pds<'a>(full_pds_text: &'a str, pds_id: &str) -> Option<&'a str> {
+    let mut position = 0usize;
+    while let Ok((current_pds_id, pds_value)) = pds_at(full_pds_text, position) {
+        if current_pds_id == pds_id {
+            return Some(pds_value);
         }
-    } else {
-        None
+
+        position = position + PDS_LEN_SIZE + PDS_ID_SIZE + pds_value.len();
+    }
+    None
+}
+
+/// Reads the id and value of the PDS at `position`, or the reason why it isn't a PDS
+fn pds_at(pds_buffer: &str, position: usize) -> std::result::Result<(&str, &str), String> {
+    let size_position = position + PDS_ID_SIZE;
+    let value_position = size_position + PDS_LEN_SIZE;
+
+    let (pds_id, pds_size) = match (
+        pds_buffer.get(position..size_position),
+        pds_buffer.get(size_position..value_position),
+    ) {
+        (Some(pds_id), Some(pds_size)) => (pds_id, pds_size),
+        _ => return Err(format!("{} characters left, too short for a pds", pds_buffer.len() - position)),
+    };
+
+    if !pds_id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid pds id {:?}", pds_id));
+    }
+
+    let pds_size = match pds_size.chars().all(|c| c.is_ascii_digit()) {
+        true => pds_size.parse::<usize>().unwrap_or_default(),
+        false => return Err(format!("invalid length {:?} for pds {}", pds_size, pds_id)),
+    };
+
+    match pds_buffer.get(value_position..value_position + pds_size) {
+        Some(pds_value) => Ok((pds_id, pds_value)),
+        None => Err(format!(
+            "pds {} has {} characters, but only {} are left",
+            pds_id,
+            pds_size,
+            pds_buffer.len().saturating_sub(value_position)
+        )),
     }
 }
 
@@ -70,3 +478,60 @@ fn test_pds_reading_from_t121() {
     let pds_values = get_pds_values(&additional_data).unwrap().unwrap();
     assert_eq!(pds_values.get("0300").unwrap(), "0012303040000002337904401");
 }
+
+#[test]
+fn test_pds_names_by_profile() {
+    assert_eq!(pds_name(Profile::Mastercard, "0105"), Some("File ID"));
+    assert_eq!(pds_name(Profile::Elo, "0105"), Some("File ID"));
+    assert_eq!(pds_name(Profile::Mastercard, "0181"), Some("Installment Payment Data"));
+    assert_eq!(pds_name(Profile::Elo, "0181"), Some("Installment Payment Data (Parcelado)"));
+    assert_eq!(pds_name(Profile::Elo, "9999"), None);
+}
+
+#[test]
+fn test_pds_collection_keeps_order_and_malformed_tail() {
+    let mut pds = PdsCollection::parse(48, "0158003DMC0023003POI0105009ABC");
+
+    assert_eq!(pds.keys().collect::<Vec<_>>(), vec!["0158", "0023"]);
+    assert_eq!(pds.entry("0023").unwrap().offset, Some(10));
+    assert_eq!(pds.malformed().len(), 1);
+    assert_eq!(pds.malformed()[0].offset, 20);
+    assert_eq!(pds.malformed()[0].content, "0105009ABC");
+
+    assert_eq!(pds.insert("0023", "ATM"), Some("POI".to_string()));
+    assert!(pds.insert("0002", "MCG").is_none());
+    assert_eq!(pds.remove("0158"), Some("DMC".to_string()));
+    assert_eq!(pds.encode(48), "0023003ATM0002003MCG0105009ABC");
+    assert_eq!(pds.entry("0023").unwrap().offset, Some(0));
+    assert_eq!(pds.malformed()[0].offset, 20);
+    assert_eq!(pds["0002"], "MCG");
+
+    let pds = PdsCollection::parse(48, "0023003POI00A");
+    assert_eq!(pds.len(), 1);
+    assert_eq!(pds.malformed()[0].reason, "3 characters left, too short for a pds");
+    assert!(PdsCollection::parse(48, "0023A03POI").malformed()[0].reason.starts_with("invalid length"));
+    assert_eq!(PdsCollection::parse(48, "0023003POI"), PdsCollection::from([("0023", "POI")]));
+}
+
+#[test]
+fn test_pds_distribution_never_splits_a_pds() {
+    let mut pds = PdsCollection::from([("0023", "POI"), ("0158", "DMC"), ("0165", "M")]);
+    pds.distribute(&[(48, 20), (62, 10), (123, 10)]).unwrap();
+
+    assert_eq!(pds.encode(48), "0023003POI0158003DMC");
+    assert_eq!(pds.encode(62), "0165001M");
+    assert_eq!(pds.encode(123), "");
+    assert_eq!(pds.entry("0165").unwrap().offset, Some(0));
+
+    // a new pds goes after the last one, on its data element
+    pds.insert("0002", "A");
+    assert_eq!(pds.encode(62), "0165001M0002001A");
+    assert!(pds.distribute(&[(48, 20), (62, 10)]).unwrap_err().to_string().contains("pds 0002 doesn't fit on DE 48, 62"));
+
+    let mut pds = PdsCollection::parse(48, "0023003POI00A");
+    pds.insert("0158", "DMC");
+    pds.distribute(&[(48, 14), (62, 10)]).unwrap();
+    assert_eq!(pds.encode(48), "0023003POI00A");
+    assert_eq!(pds.encode(62), "0158003DMC");
+    assert_eq!(pds.malformed()[0].offset, 10);
+}
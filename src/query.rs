@@ -0,0 +1,242 @@
+use crate::{Iso8583File, Message};
+use eyre::{eyre, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::ops::Not;
+use strum::EnumProperty;
+
+/// What a query looks at on each message, also used as the key of the indexes built by `Iso8583File::build_index`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Selector {
+    Mti,
+    /// DE by its number, e.g `De(24)` for the function code
+    De(usize),
+    /// PDS by its id, e.g `Pds("0105")`
+    Pds(String),
+    /// Category name, e.g `first_presentments`
+    Category,
+}
+
+impl Selector {
+    pub(crate) fn value(&self, message: &Message) -> Option<String> {
+        match self {
+            Selector::Mti => Some(message.mti.clone()),
+            Selector::De(field_number) => message.de(*field_number).map(|value| value.get_string()),
+            Selector::Pds(pds_id) => message.pds.get(pds_id).cloned(),
+            Selector::Category => message.category.get_str("name").map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    Exists,
+    Equals(String),
+    OneOf(Vec<String>),
+    /// Inclusive numeric range, non numeric values never match
+    Between(u64, u64),
+    StartsWith(String),
+    Matches(Regex),
+}
+
+impl Predicate {
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            Predicate::Exists => true,
+            Predicate::Equals(expected) => value == expected,
+            Predicate::OneOf(expected) => expected.iter().any(|expected| value == expected),
+            Predicate::Between(min, max) => value
+                .trim()
+                .parse::<u64>()
+                .map(|number| (*min..=*max).contains(&number))
+                .unwrap_or(false),
+            Predicate::StartsWith(prefix) => value.starts_with(prefix.as_str()),
+            Predicate::Matches(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// Composable filter over messages, e.g
+///
+/// ```
+/// use iso8583::query::Query;
+///
+/// let chargebacks_or_big_amounts = Query::category("first_chargebacks")
+///     .or(Query::de(4).between(100_000, u64::MAX))
+///     .and(!Query::pds("0025").exists());
+/// ```
+#[derive(Debug, Clone)]
+pub enum Query {
+    Match(Selector, Predicate),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+/// A selector waiting for its predicate
+pub struct QueryBuilder {
+    selector: Selector,
+}
+
+impl QueryBuilder {
+    fn predicate(self, predicate: Predicate) -> Query {
+        Query::Match(self.selector, predicate)
+    }
+
+    pub fn exists(self) -> Query {
+        self.predicate(Predicate::Exists)
+    }
+
+    pub fn eq(self, value: &str) -> Query {
+        self.predicate(Predicate::Equals(value.to_string()))
+    }
+
+    pub fn one_of(self, values: &[&str]) -> Query {
+        self.predicate(Predicate::OneOf(values.iter().map(|value| value.to_string()).collect()))
+    }
+
+    pub fn between(self, min: u64, max: u64) -> Query {
+        self.predicate(Predicate::Between(min, max))
+    }
+
+    pub fn starts_with(self, prefix: &str) -> Query {
+        self.predicate(Predicate::StartsWith(prefix.to_string()))
+    }
+
+    pub fn matches(self, pattern: &str) -> Result<Query> {
+        let regex = Regex::new(pattern).map_err(|e| eyre!("invalid query pattern {:?}: {}", pattern, e))?;
+        Ok(self.predicate(Predicate::Matches(regex)))
+    }
+}
+
+impl Query {
+    pub fn mti() -> QueryBuilder {
+        QueryBuilder { selector: Selector::Mti }
+    }
+
+    pub fn de(field_number: usize) -> QueryBuilder {
+        QueryBuilder {
+            selector: Selector::De(field_number),
+        }
+    }
+
+    pub fn pds(pds_id: &str) -> QueryBuilder {
+        QueryBuilder {
+            selector: Selector::Pds(pds_id.to_string()),
+        }
+    }
+
+    pub fn category(name: &str) -> Query {
+        Query::Match(Selector::Category, Predicate::Equals(name.to_string()))
+    }
+
+    pub fn and(self, other: Query) -> Query {
+        match self {
+            Query::And(mut queries) => {
+                queries.push(other);
+                Query::And(queries)
+            }
+            query => Query::And(vec![query, other]),
+        }
+    }
+
+    pub fn or(self, other: Query) -> Query {
+        match self {
+            Query::Or(mut queries) => {
+                queries.push(other);
+                Query::Or(queries)
+            }
+            query => Query::Or(vec![query, other]),
+        }
+    }
+
+    pub fn matches(&self, message: &Message) -> bool {
+        match self {
+            Query::Match(selector, predicate) => selector
+                .value(message)
+                .map(|value| predicate.accepts(&value))
+                .unwrap_or(false),
+            Query::And(queries) => queries.iter().all(|query| query.matches(message)),
+            Query::Or(queries) => queries.iter().any(|query| query.matches(message)),
+            Query::Not(query) => !query.matches(message),
+        }
+    }
+}
+
+impl Not for Query {
+    type Output = Query;
+
+    fn not(self) -> Query {
+        Query::Not(Box::new(self))
+    }
+}
+
+impl Iso8583File {
+    /// Messages matching the query, borrowed from the file
+    pub fn filter<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = &'a Message> + 'a {
+        self.messages.iter().filter(move |message| query.matches(message))
+    }
+
+    /// Indexes of the messages matching the query
+    pub fn find_indexes(&self, query: &Query) -> Vec<usize> {
+        self.messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| query.matches(message))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Indexes the messages by the values of a DE or PDS, e.g `Selector::De(31)` for ARN lookups,
+    /// which has to be rebuilt when the messages change
+    pub fn build_index(&mut self, key: Selector) {
+        let mut value_index: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (index, message) in self.messages.iter().enumerate() {
+            if let Some(value) = key.value(message) {
+                value_index.entry(value).or_default().push(index);
+            }
+        }
+
+        self.value_indexes.insert(key, value_index);
+    }
+
+    /// Indexes of the messages whose key has the given value, using an index built with `build_index`
+    pub fn find_by(&self, key: &Selector, value: &str) -> Result<&[usize]> {
+        let value_index = self
+            .value_indexes
+            .get(key)
+            .ok_or_else(|| eyre!("no index built for {:?}", key))?;
+
+        Ok(value_index.get(value).map(Vec::as_slice).unwrap_or_default())
+    }
+}
+
+#[test]
+fn test_query_composition() {
+    use crate::builder::MessageBuilder;
+    use crate::iso_specs::IsoSpecs;
+
+    let specs = IsoSpecs::new();
+    let message = MessageBuilder::new(&specs)
+        .mti("1240")
+        .de(2, "5499999999999998")
+        .de(4, "15000")
+        .de(24, "200")
+        .pds("0023", "POI")
+        .build()
+        .unwrap();
+
+    assert!(Query::category("first_presentments").matches(&message));
+    assert!(Query::de(24).one_of(&["200", "205"]).matches(&message));
+    assert!(Query::de(4).between(10_000, 20_000).matches(&message));
+    assert!(!Query::de(4).between(0, 100).matches(&message));
+    assert!(Query::de(2).starts_with("5499").matches(&message));
+    assert!(Query::pds("0023").matches("^P.I$").unwrap().matches(&message));
+    assert!(Query::de(2).matches("[").is_err());
+
+    assert!(!Query::de(31).exists().matches(&message));
+    assert!((!Query::de(31).exists()).matches(&message));
+    assert!(Query::mti().eq("1644").or(Query::pds("0023").eq("POI")).matches(&message));
+    assert!(!Query::mti().eq("1240").and(Query::de(24).eq("205")).matches(&message));
+}
@@ -0,0 +1,649 @@
+use crate::data_elements::DataElements;
+use crate::decoders::{Arn, Pan};
+use crate::iso_field::{FieldSizeType, IPMValue, IsoField};
+use crate::iso_specs::{Category, FileType, IsoSpecs};
+use crate::{Iso8583File, Message};
+use strum::EnumProperty;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ViolationKind {
+    MalformedPan,
+    InvalidPanCheckDigit,
+    MalformedArn,
+    InvalidArnCheckDigit,
+    InvalidCharacters,
+    LengthExceeded,
+    LengthMismatch,
+    MissingDataElement,
+    ProhibitedDataElement,
+    UnexpectedDataElement,
+    MissingPds,
+    ProhibitedPds,
+    UnexpectedPds,
+    MalformedInstallment,
+    InstallmentAmountMismatch,
+    CategoryNotAllowedOnFile,
+    DuplicateMessageNumber,
+    MessageNumberOutOfOrder,
+    MessageNumberGap,
+    TrailerCountMismatch,
+    MalformedPds,
+}
+
+/// A single problem found on a message, pointing to the message position inside the file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    pub message_index: usize,
+    pub field_id: String,
+    pub kind: ViolationKind,
+    pub detail: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "message {} - {}: {:?} ({})",
+            self.message_index, self.field_id, self.kind, self.detail
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn violations_for(&self, message_index: usize) -> Vec<&Violation> {
+        self.violations
+            .iter()
+            .filter(|violation| violation.message_index == message_index)
+            .collect()
+    }
+}
+
+fn violation(message_index: usize, field_id: &str, kind: ViolationKind, detail: String) -> Violation {
+    Violation {
+        message_index,
+        field_id: field_id.to_string(),
+        kind,
+        detail,
+    }
+}
+
+/// Runs every validation over a single message: field formats, PDS layout, category presence
+/// rules and PAN/ARN check digits and installments
+pub fn validate_message(specs: &IsoSpecs, message_index: usize, message: &Message) -> Vec<Violation> {
+    let mut violations = validate_fields_format(specs, message_index, message);
+    violations.append(&mut validate_pds_layout(message_index, message));
+    violations.append(&mut validate_category_rules(message_index, message));
+    violations.append(&mut validate_check_digits(message_index, message));
+    violations.append(&mut validate_installment(message_index, message));
+    violations
+}
+
+/// Checks each DE content against the char type and length of its spec, numbers included
+pub fn validate_fields_format(specs: &IsoSpecs, message_index: usize, message: &Message) -> Vec<Violation> {
+    let mut violations = vec![];
+
+    let raw_data_elements = message.category.raw_data_elements();
+
+    for (field_number, value) in message.data_elements.iter() {
+        let field_id = DataElements::label_id(field_number);
+
+        // DE 1 is the secondary bitmap, which is described by the "bitmaps" spec
+        let iso_field = match specs.field(&field_id) {
+            Some(iso_field) if field_number != 1 => iso_field,
+            _ => continue,
+        };
+
+        let (content, length) = match value {
+            IPMValue::String(s) => (Some(s.to_string()), s.chars().count()),
+            IPMValue::u64(num) => (Some(num.to_string()), num.to_string().len()),
+            IPMValue::Binary(b) => (None, b.len()),
+        };
+
+        // numbers lose their leading zeros once read, so their length is the one on the record, if any
+        let length = message
+            .raw
+            .as_ref()
+            .and_then(|raw| raw.content_length(iso_field, field_number, value))
+            .unwrap_or(length);
+
+        if length > iso_field.length {
+            violations.push(violation(
+                message_index,
+                &field_id,
+                ViolationKind::LengthExceeded,
+                format!("{} has {} characters, max is {}", iso_field.label, length, iso_field.length),
+            ));
+        }
+
+        // fixed fields are padded on encoding, apart from binary ones
+        let is_binary = matches!(value, IPMValue::Binary(_));
+        if iso_field.size_type == FieldSizeType::Fixed && is_binary && length < iso_field.length {
+            violations.push(violation(
+                message_index,
+                &field_id,
+                ViolationKind::LengthMismatch,
+                format!("{} has {} bytes, expected {}", iso_field.label, length, iso_field.length),
+            ));
+        }
+
+        if let Some(content) = content {
+            if !raw_data_elements.contains(&field_id.as_str()) && !iso_field.char_type.accepts(&content) {
+                violations.push(invalid_characters(message_index, iso_field, &content));
+            }
+        }
+    }
+
+    violations
+}
+
+fn invalid_characters(message_index: usize, iso_field: &IsoField, content: &str) -> Violation {
+    violation(
+        message_index,
+        &iso_field.label_id,
+        ViolationKind::InvalidCharacters,
+        format!("{} should be {:?}: {:?}", iso_field.label, iso_field.char_type.as_str(), content),
+    )
+}
+
+/// Reports the content of the PDS data elements that couldn't be read as PDS
+pub fn validate_pds_layout(message_index: usize, message: &Message) -> Vec<Violation> {
+    message
+        .pds
+        .malformed()
+        .iter()
+        .map(|malformed| {
+            violation(
+                message_index,
+                &DataElements::label_id(malformed.data_element),
+                ViolationKind::MalformedPds,
+                format!("{} at position {}", malformed.reason, malformed.offset),
+            )
+        })
+        .collect()
+}
+
+/// Checks the mandatory and prohibited DEs and PDS of the message category
+pub fn validate_category_rules(message_index: usize, message: &Message) -> Vec<Violation> {
+    let mut violations = vec![];
+    let category_name = message.category.get_str("name").unwrap_or_default();
+
+    let de_rules = message.category.data_element_rules();
+
+    for field_id in &de_rules.mandatory {
+        if !message.data_elements.contains_key(field_id) {
+            violations.push(violation(
+                message_index,
+                field_id,
+                ViolationKind::MissingDataElement,
+                format!("mandatory on {}", category_name),
+            ));
+        }
+    }
+
+    for field_id in &de_rules.prohibited {
+        if message.data_elements.contains_key(field_id) {
+            violations.push(violation(
+                message_index,
+                field_id,
+                ViolationKind::ProhibitedDataElement,
+                format!("not allowed on {}", category_name),
+            ));
+        }
+    }
+
+    // categories listing their conditional DEs allow no other DE, apart from DE 1 (secondary bitmap)
+    if !de_rules.conditional.is_empty() {
+        for (field_number, _) in message.data_elements.iter() {
+            let field_id = DataElements::label_id(field_number);
+            if field_number != 1
+                && !de_rules.mandatory.contains(&field_id.as_str())
+                && !de_rules.conditional.contains(&field_id.as_str())
+                && !de_rules.prohibited.contains(&field_id.as_str())
+            {
+                violations.push(violation(
+                    message_index,
+                    &field_id,
+                    ViolationKind::UnexpectedDataElement,
+                    format!("neither mandatory nor conditional on {}", category_name),
+                ));
+            }
+        }
+    }
+
+    let pds_rules = message.category.pds_rules();
+
+    for pds_id in &pds_rules.mandatory {
+        if !message.pds.contains_key(pds_id) {
+            violations.push(violation(
+                message_index,
+                pds_id,
+                ViolationKind::MissingPds,
+                format!("mandatory on {}", category_name),
+            ));
+        }
+    }
+
+    for pds_id in &pds_rules.prohibited {
+        if message.pds.contains_key(pds_id) {
+            violations.push(violation(
+                message_index,
+                pds_id,
+                ViolationKind::ProhibitedPds,
+                format!("not allowed on {}", category_name),
+            ));
+        }
+    }
+
+    if !pds_rules.conditional.is_empty() {
+        for pds_id in message.pds.keys() {
+            if !pds_rules.mandatory.contains(&pds_id.as_str())
+                && !pds_rules.conditional.contains(&pds_id.as_str())
+                && !pds_rules.prohibited.contains(&pds_id.as_str())
+            {
+                violations.push(violation(
+                    message_index,
+                    pds_id,
+                    ViolationKind::UnexpectedPds,
+                    format!("neither mandatory nor conditional on {}", category_name),
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+/// Checks DE 2 (skipping masked PANs) and DE 31 check digits
+pub fn validate_check_digits(message_index: usize, message: &Message) -> Vec<Violation> {
+    let mut violations = vec![];
+    let violation = |field_id: &str, kind: ViolationKind, detail: String| {
+        violation(message_index, field_id, kind, detail)
+    };
+
+    if let Some(pan) = message.data_elements.get("002") {
+        match Pan::decode(&pan.get_string()) {
+            Ok(pan) if pan.has_valid_check_digit() == Some(false) => violations.push(violation(
+                "002",
+                ViolationKind::InvalidPanCheckDigit,
+                format!("{} fails the luhn check", pan.value),
+            )),
+            Ok(_) => (),
+            Err(e) => violations.push(violation("002", ViolationKind::MalformedPan, e.to_string())),
+        }
+    }
+
+    if let Some(arn) = message.data_elements.get("031") {
+        match Arn::decode(&arn.get_string()) {
+            Ok(arn) if !arn.has_valid_check_digit() => violations.push(violation(
+                "031",
+                ViolationKind::InvalidArnCheckDigit,
+                format!("{} fails the luhn check", arn.value),
+            )),
+            Ok(_) => (),
+            Err(e) => violations.push(violation("031", ViolationKind::MalformedArn, e.to_string())),
+        }
+    }
+
+    violations
+}
+
+/// Checks the installment data (PDS 0181) against the transaction amount (DE 4)
+pub fn validate_installment(message_index: usize, message: &Message) -> Vec<Violation> {
+    let installment = match message.installment() {
+        Ok(Some(installment)) => installment,
+        Ok(None) => return vec![],
+        Err(e) => return vec![violation(message_index, "0181", ViolationKind::MalformedInstallment, e.to_string())],
+    };
+
+    match message.data_elements.get("004") {
+        Some(IPMValue::u64(amount)) if !installment.matches_amount(*amount) => vec![violation(
+            message_index,
+            "0181",
+            ViolationKind::InstallmentAmountMismatch,
+            format!(
+                "{} installments sum {}, but the transaction amount is {}",
+                installment.number_of_installments,
+                installment.total_amount(),
+                amount
+            ),
+        )],
+        _ => vec![],
+    }
+}
+
+/// Checks the message category against the categories its file type can carry
+pub fn validate_file_type(file_type: FileType, message_index: usize, message: &Message) -> Vec<Violation> {
+    if file_type.allows(&message.category) {
+        return vec![];
+    }
+
+    vec![violation(
+        message_index,
+        "024",
+        ViolationKind::CategoryNotAllowedOnFile,
+        format!(
+            "{} not allowed on {} files",
+            message.category.get_str("name").unwrap_or_default(),
+            file_type.get_str("name").unwrap_or_default()
+        ),
+    )]
+}
+
+/// Checks that message numbers (DE 71) increase by one across the file, reporting duplicates,
+/// numbers going backwards and gaps (dropped records), and that the trailer message count
+/// (PDS 0306) matches the number of messages
+///
+/// Messages without a numeric DE 71 are skipped, their DE 71 being checked by the category rules
+pub fn validate_message_numbers(messages: &[Message]) -> Vec<Violation> {
+    let mut violations = vec![];
+    let mut previous_numbers: HashMap<u64, usize> = HashMap::new();
+    let mut last_number = 0u64;
+
+    for (message_index, message) in messages.iter().enumerate() {
+        let message_number = match message.de(71).and_then(|value| value.get_string().trim().parse::<u64>().ok()) {
+            Some(message_number) => message_number,
+            None => continue,
+        };
+
+        if let Some(previous_index) = previous_numbers.get(&message_number) {
+            violations.push(violation(
+                message_index,
+                "071",
+                ViolationKind::DuplicateMessageNumber,
+                format!("message number {} already used by message {}", message_number, previous_index),
+            ));
+            continue;
+        }
+        previous_numbers.insert(message_number, message_index);
+
+        if message_number < last_number {
+            violations.push(violation(
+                message_index,
+                "071",
+                ViolationKind::MessageNumberOutOfOrder,
+                format!("message number {} comes after {}", message_number, last_number),
+            ));
+            continue;
+        }
+
+        if message_number > last_number + 1 {
+            violations.push(violation(
+                message_index,
+                "071",
+                ViolationKind::MessageNumberGap,
+                format!(
+                    "expected message number {}, found {} ({} missing)",
+                    last_number + 1,
+                    message_number,
+                    message_number - last_number - 1
+                ),
+            ));
+        }
+        last_number = message_number;
+    }
+
+    for (message_index, message) in messages.iter().enumerate() {
+        if !matches!(message.category, Category::Trailer) {
+            continue;
+        }
+
+        if let Some(messages_count) = message.pds.get("0306") {
+            if messages_count.trim().parse::<usize>().ok() != Some(messages.len()) {
+                violations.push(violation(
+                    message_index,
+                    "0306",
+                    ViolationKind::TrailerCountMismatch,
+                    format!("trailer counts {:?} messages, but the file has {}", messages_count, messages.len()),
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+impl Iso8583File {
+    pub fn validate(&self) -> ValidationReport {
+        self.validate_with_specs(&IsoSpecs::new())
+    }
+
+    /// Validates the messages against the specs they were parsed with, e.g `IsoSpecs::iso8583_1987()`,
+    /// against the categories allowed by the file type and, on files with a header, their message
+    /// numbers sequence
+    pub fn validate_with_specs(&self, specs: &IsoSpecs) -> ValidationReport {
+        let file_type = self.file_type();
+        let mut violations: Vec<Violation> = self
+            .messages
+            .iter()
+            .enumerate()
+            .flat_map(|(index, message)| {
+                let mut violations = validate_message(specs, index, message);
+                violations.append(&mut validate_file_type(file_type, index, message));
+                violations
+            })
+            .collect();
+
+        if self.categories_indexes.contains_key("headers") {
+            violations.append(&mut validate_message_numbers(&self.messages));
+        }
+
+        ValidationReport { violations }
+    }
+}
+
+#[test]
+fn test_pan_and_arn_violations() {
+    use crate::iso_field::IPMValue;
+    use crate::iso_specs::Category;
+    use crate::pds::PdsCollection;
+
+    let message = Message {
+        mti: "1240".to_string(),
+        category: Category::FirstPresentment,
+        primary_bitmap: [0u8; 8],
+        data_elements: DataElements::from([
+            (2, IPMValue::String("5499999999999999".to_string())),
+            (31, IPMValue::String("75123453123000000000015".to_string())),
+        ]),
+        pds: PdsCollection::new(),
+        raw: None,
+    };
+
+    let violations = validate_check_digits(3, &message);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].message_index, 3);
+    assert_eq!(violations[0].kind, ViolationKind::InvalidPanCheckDigit);
+
+    let mut masked = message;
+    masked.data_elements.set_de(2, IPMValue::String("549999******9999".to_string()));
+    masked.data_elements.set_de(31, IPMValue::String("7512345".to_string()));
+
+    let violations = validate_check_digits(0, &masked);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].kind, ViolationKind::MalformedArn);
+}
+
+#[test]
+fn test_format_and_presence_violations() {
+    use crate::iso_specs::Category;
+    use crate::pds::PdsCollection;
+
+    let message = Message {
+        mti: "1644".to_string(),
+        category: Category::Header,
+        primary_bitmap: [0u8; 8],
+        data_elements: DataElements::from([
+            (2, IPMValue::String("5499999999999998".to_string())),
+            (24, IPMValue::String("6-7".to_string())),
+            (71, IPMValue::u64(123456789)),
+        ]),
+        pds: PdsCollection::from([("0122", "T")]),
+        raw: None,
+    };
+
+    let violations = validate_message(&IsoSpecs::new(), 0, &message);
+    let kinds: Vec<(&str, &ViolationKind)> = violations
+        .iter()
+        .map(|violation| (violation.field_id.as_str(), &violation.kind))
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            ("024", &ViolationKind::InvalidCharacters),
+            ("071", &ViolationKind::LengthExceeded),
+            ("048", &ViolationKind::MissingDataElement),
+            ("002", &ViolationKind::ProhibitedDataElement),
+            ("0105", &ViolationKind::MissingPds),
+        ]
+    );
+}
+
+#[test]
+fn test_numeric_content_and_conditional_violations() {
+    use crate::iso_specs::Category;
+    use crate::pds::PdsCollection;
+
+    let mut message = Message {
+        mti: "1644".to_string(),
+        category: Category::FinancialDetailAddendum,
+        primary_bitmap: [0u8; 8],
+        data_elements: DataElements::from([
+            (4, IPMValue::u64(1500)),
+            (24, IPMValue::String("696".to_string())),
+            (33, IPMValue::String("002001".to_string())),
+            (41, IPMValue::Binary(vec![0xF1, 0xF2])),
+            (48, IPMValue::String("".to_string())),
+            (71, IPMValue::u64(1)),
+            (97, IPMValue::u64(100)),
+        ]),
+        pds: PdsCollection::new(),
+        raw: None,
+    };
+
+    let violations = validate_message(&IsoSpecs::new(), 0, &message);
+    let kinds: Vec<(&str, &ViolationKind)> = violations
+        .iter()
+        .map(|violation| (violation.field_id.as_str(), &violation.kind))
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            ("041", &ViolationKind::LengthMismatch),
+            ("097", &ViolationKind::InvalidCharacters),
+            ("004", &ViolationKind::UnexpectedDataElement),
+            ("041", &ViolationKind::UnexpectedDataElement),
+            ("097", &ViolationKind::UnexpectedDataElement),
+        ]
+    );
+
+    message.category = Category::Header;
+    message.pds.insert("0105", "001");
+    message.pds.insert("0122", "T");
+    message.pds.insert("0301", "1");
+    let violations = validate_category_rules(0, &message);
+    assert!(!violations.iter().any(|violation| violation.kind == ViolationKind::UnexpectedDataElement));
+    assert_eq!(violations.last().unwrap().field_id, "0301");
+    assert_eq!(violations.last().unwrap().kind, ViolationKind::UnexpectedPds);
+}
+
+#[test]
+fn test_installment_violations() {
+    use crate::iso_specs::Category;
+    use crate::pds::PdsCollection;
+
+    let mut message = Message {
+        mti: "1240".to_string(),
+        category: Category::FirstPresentment,
+        primary_bitmap: [0u8; 8],
+        data_elements: DataElements::from([(4, IPMValue::u64(100))]),
+        pds: PdsCollection::from([("0181", "210300000000000000034000000000033")]),
+        raw: None,
+    };
+    assert!(validate_installment(0, &message).is_empty());
+
+    message.data_elements.set_de(4, IPMValue::u64(150));
+    assert_eq!(validate_installment(0, &message)[0].kind, ViolationKind::InstallmentAmountMismatch);
+
+    message.pds.insert("0181", "21");
+    assert_eq!(validate_installment(0, &message)[0].kind, ViolationKind::MalformedInstallment);
+}
+
+#[test]
+fn test_file_type_violations() {
+    use crate::iso_specs::Category;
+    use crate::pds::PdsCollection;
+
+    let message = Message {
+        mti: "1240".to_string(),
+        category: Category::FirstPresentment,
+        primary_bitmap: [0u8; 8],
+        data_elements: DataElements::new(),
+        pds: PdsCollection::new(),
+        raw: None,
+    };
+
+    assert!(validate_file_type(FileType::OutboundClearing, 0, &message).is_empty());
+    assert!(validate_file_type(FileType::Unknown, 0, &message).is_empty());
+    assert_eq!(
+        validate_file_type(FileType::Notification, 0, &message)[0].kind,
+        ViolationKind::CategoryNotAllowedOnFile
+    );
+}
+
+#[test]
+fn test_message_numbers_violations() {
+    use crate::pds::PdsCollection;
+
+    let message = |category: Category, message_number: u64| Message {
+        mti: "1240".to_string(),
+        category,
+        primary_bitmap: [0u8; 8],
+        data_elements: DataElements::from([(71, IPMValue::u64(message_number))]),
+        pds: PdsCollection::new(),
+        raw: None,
+    };
+
+    let mut messages: Vec<Message> = (1..=5)
+        .map(|message_number| message(Category::FirstPresentment, message_number))
+        .collect();
+    let mut trailer = message(Category::Trailer, 6);
+    trailer.pds.insert("0306", "00000006");
+    messages.push(trailer);
+    assert!(validate_message_numbers(&messages).is_empty());
+
+    for (index, message_number) in [(2, 4), (3, 3), (4, 3)] {
+        messages[index].data_elements.set_de(71, IPMValue::u64(message_number));
+    }
+    messages[5].pds.insert("0306", "00000007");
+
+    let violations = validate_message_numbers(&messages);
+    let kinds: Vec<(usize, &ViolationKind)> = violations
+        .iter()
+        .map(|violation| (violation.message_index, &violation.kind))
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            (2, &ViolationKind::MessageNumberGap),
+            (3, &ViolationKind::MessageNumberOutOfOrder),
+            (4, &ViolationKind::DuplicateMessageNumber),
+            (5, &ViolationKind::MessageNumberGap),
+            (5, &ViolationKind::TrailerCountMismatch),
+        ]
+    );
+    assert_eq!(violations[4].field_id, "0306");
+}
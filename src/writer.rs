@@ -0,0 +1,186 @@
+use crate::encoding;
+use crate::data_elements::DataElements;
+use crate::file_utils;
+use crate::iso_field::{IPMValue, IsoField};
+use crate::iso_msg::Bitmap;
+use crate::iso_specs::IsoSpecs;
+use crate::Message;
+use eyre::{eyre, Result};
+use strum::EnumProperty;
+
+/// Computes the bitmaps for a set of DE numbers, following the bitmap layout of the specs
+///
+/// DE 1 (and DE 65 when there is a tertiary bitmap) are bitmaps themselves, so those are skipped
+pub fn compute_bitmap_for<I>(specs: &IsoSpecs, field_numbers: I) -> Result<Bitmap>
+where
+    I: IntoIterator<Item = usize>,
+{
+    let mut present_field_numbers = vec![];
+
+    for field_number in field_numbers {
+        if field_number == 1 || (field_number == 65 && specs.bitmap_layout.tertiary) {
+            continue;
+        }
+
+        if !(2..specs.specs.len()).contains(&field_number) {
+            return Err(eyre!("data element {} is out of the specs range", field_number));
+        }
+
+        present_field_numbers.push(field_number);
+    }
+
+    Ok(specs.bitmap_layout.bitmap_for(present_field_numbers))
+}
+
+/// Content of a value as ASCII characters, padding fixed fields when needed (zeros on the left
+/// for numbers, spaces on the right otherwise), before any encoding of the field is applied
+pub fn pad_content(iso_field: &IsoField, value: &IPMValue) -> Result<Vec<u8>> {
+    let mut content = match value {
+        IPMValue::u64(num) => num.to_string().into_bytes(),
+        IPMValue::String(s) => s.as_bytes().to_vec(),
+        IPMValue::Binary(b) => b.to_vec(),
+    };
+
+    if content.len() > iso_field.length {
+        return Err(eyre!(
+            "{} has {} bytes, max is {}",
+            iso_field.label,
+            content.len(),
+            iso_field.length
+        ));
+    }
+
+    let padding_size = match iso_field.length_prefix_digits() {
+        0 => iso_field.length - content.len(),
+        _ => 0,
+    };
+    if padding_size > 0 {
+        match value {
+            IPMValue::Binary(_) => {
+                return Err(eyre!("{} should have exactly {} bytes", iso_field.label, iso_field.length));
+            }
+            _ if iso_field.char_type.get_str("content_type") == Some("number") => {
+                let mut padded = vec![b'0'; padding_size];
+                padded.append(&mut content);
+                content = padded;
+            }
+            _ => content.resize(iso_field.length, b' '),
+        }
+    }
+
+    Ok(content)
+}
+
+/// Encodes a value as its length prefix (for variable fields) followed by its content, padded
+/// by `pad_content`
+///
+/// Both the prefix and the content follow the encodings of the field, e.g BCD or EBCDIC
+pub fn encode_field(iso_field: &IsoField, value: &IPMValue) -> Result<Vec<u8>> {
+    let content = pad_content(iso_field, value)?;
+    let prefix_digits = iso_field.length_prefix_digits();
+
+    if prefix_digits > 0 {
+        let mut encoded = encoding::encode_length_prefix(iso_field.length_encoding, content.len(), prefix_digits)?;
+        encoded.append(&mut encoding::encode_content(iso_field.content_encoding, &content)?);
+        return Ok(encoded);
+    }
+
+    encoding::encode_content(iso_field.content_encoding, &content)
+}
+
+/// Encodes a message as MTI, bitmaps and its DEs in ascending order
+///
+/// Bitmaps are computed from the DEs present on the message, so changes on
+/// `Message.data_elements` are always reflected. Messages parsed by `parse_file_lossless` reuse
+/// the bytes of their unchanged parts
+pub fn encode_message(specs: &IsoSpecs, message: &Message) -> Result<Vec<u8>> {
+    if let Some(raw) = &message.raw {
+        return raw.encode(specs, message);
+    }
+
+    if message.mti.len() != 4 {
+        return Err(eyre!("invalid mti {:?}", message.mti));
+    }
+
+    let mut encoded = encode_field(&specs.specs[0], &IPMValue::String(message.mti.clone()))?;
+
+    let bitmap = compute_bitmap_for(specs, message.data_elements.numbers())?;
+    encoded.append(&mut specs.bitmap_layout.encode(&bitmap));
+
+    let data_elements = message
+        .data_elements
+        .iter()
+        .filter(|(field_number, _)| *field_number != 1 && !(*field_number == 65 && specs.bitmap_layout.tertiary));
+
+    for (field_number, value) in data_elements {
+        let field_id = DataElements::label_id(field_number);
+        let iso_field = specs
+            .field(&field_id)
+            .ok_or_else(|| eyre!("no spec found for data element {:?}", field_id))?;
+
+        encoded.append(&mut encode_field(iso_field, value)?);
+    }
+
+    Ok(encoded)
+}
+
+/// Encodes all messages, prefixing each one with its RDW and optionally applying the 1014 blocking
+pub fn write_file(messages: &[Message], blocked: bool) -> Result<Vec<u8>> {
+    write_file_with_specs(messages, blocked, &IsoSpecs::new())
+}
+
+/// Same as `write_file`, encoding the messages with the given specs
+pub fn write_file_with_specs(messages: &[Message], blocked: bool, specs: &IsoSpecs) -> Result<Vec<u8>> {
+    let records = messages
+        .iter()
+        .map(|message| encode_message(specs, message))
+        .collect::<Result<Vec<Vec<u8>>>>()?;
+
+    let payload = file_utils::add_rdw_to(&records);
+
+    if blocked {
+        Ok(file_utils::block(payload))
+    } else {
+        Ok(payload)
+    }
+}
+
+#[test]
+fn test_encode_field_padding() {
+    use crate::iso_field::{FieldCharType, FieldSizeType};
+
+    let message_number = IsoField::new("Message Number", "071", FieldCharType::Iso8583_n, 8, FieldSizeType::Fixed);
+    assert_eq!(encode_field(&message_number, &IPMValue::u64(12)).unwrap(), b"00000012");
+
+    let function_code = IsoField::new("Function Code", "024", FieldCharType::Iso8583_an, 3, FieldSizeType::Fixed);
+    assert_eq!(encode_field(&function_code, &IPMValue::String("69".to_string())).unwrap(), b"69 ");
+
+    let pan = IsoField::new("Primary Account Number", "002", FieldCharType::Iso8583_ns, 19, FieldSizeType::LlVar);
+    assert_eq!(
+        encode_field(&pan, &IPMValue::String("5499999999999998".to_string())).unwrap(),
+        b"165499999999999998"
+    );
+    assert!(encode_field(&pan, &IPMValue::String("54999999999999999999".to_string())).is_err());
+}
+
+#[test]
+fn test_encode_field_with_encodings() {
+    use crate::iso_field::{FieldCharType, FieldEncoding, FieldSizeType};
+
+    let amount = IsoField::new("Amount, Txn", "004", FieldCharType::Iso8583_n, 12, FieldSizeType::Fixed)
+        .with_content_encoding(FieldEncoding::BcdLeftPadded);
+    assert_eq!(encode_field(&amount, &IPMValue::u64(1500)).unwrap(), vec![0, 0, 0, 0, 0x15, 0]);
+
+    let pan = IsoField::new("Primary Account Number", "002", FieldCharType::Iso8583_n, 19, FieldSizeType::LlVar)
+        .with_length_encoding(FieldEncoding::Binary)
+        .with_content_encoding(FieldEncoding::BcdRightPadded);
+    assert_eq!(
+        encode_field(&pan, &IPMValue::String("5499999999999999998".to_string())).unwrap(),
+        vec![0x13, 0x54, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x8F]
+    );
+
+    let name = IsoField::new("Card Acceptor Name/Location", "043", FieldCharType::Iso8583_ans, 99, FieldSizeType::LlVar)
+        .with_length_encoding(FieldEncoding::BcdLeftPadded)
+        .with_content_encoding(FieldEncoding::Ebcdic);
+    assert_eq!(encode_field(&name, &IPMValue::String("AB 1".to_string())).unwrap(), vec![0x04, 0xC1, 0xC2, 0x40, 0xF1]);
+}
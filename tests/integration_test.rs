@@ -1,5 +1,7 @@
+use iso8583::data_elements::DataElements;
 use iso8583::iso_msg::IsoMsg;
 use iso8583::iso_specs::IsoSpecs;
+use iso8583::query::Query;
 #[cfg(test)]
 use std::collections::HashMap;
 #[cfg(test)]
@@ -22,7 +24,7 @@ fn parse_r111_binary() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let _iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 }
@@ -35,7 +37,7 @@ fn parse_invalid_file() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let _iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 }
@@ -48,7 +50,7 @@ fn parse_t113_blocked_with_rdw_binary() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
@@ -67,6 +69,7 @@ fn parse_t113_blocked_with_rdw_binary() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn search_and_filter_t113_deblocked_sample() {
     let file_name = "tests/T121_sample.ipm";
     let mut file = File::open(file_name).expect("no file found");
@@ -74,15 +77,23 @@ fn search_and_filter_t113_deblocked_sample() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
-    let searched = iso8583_file.search(HashMap::from([("Function Code".to_string(), vec!["200".to_string(), "691".to_string()])]));
+    let query = Query::de(24).one_of(&["200", "691"]);
+    let expected_indexes = iso8583_file.find_indexes(&query);
+    assert!(!expected_indexes.is_empty());
 
-    dbg!(searched);
-    // assert_eq!(searched.messages.len(), 2318);
-    // let filtered = iso8583_file.filter(vec!["Message Number"]);
+    let filtered: Vec<&iso8583::Message> = iso8583_file.filter(&query).collect();
+    assert_eq!(filtered.len(), expected_indexes.len());
+    assert!(filtered.iter().all(|message| ["200", "691"].contains(&message.data_elements["024"].get_string().as_str())));
+
+    let searched = iso8583_file.clone().search(HashMap::from([("024".to_string(), vec!["200".to_string(), "691".to_string()])]));
+    assert_eq!(searched.messages.len(), expected_indexes.len());
+
+    let not_exceptions = iso8583_file.find_indexes(&(!Query::category("message_exceptions")));
+    assert_eq!(not_exceptions.len() + iso8583_file.categories_indexes["message_exceptions"].len(), iso8583_file.messages.len());
 }
 
 #[test]
@@ -94,7 +105,7 @@ fn parse_t121_deblocked_sample() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
@@ -110,12 +121,12 @@ fn parse_t121_deblocked_sample() {
         let iso_fields = IsoSpecs::define_specs();
         let iso_fields_ref = &iso_fields;
         for (k, v) in original_messages {
-            let current_label_id = if k == "001" {
+            let current_label_id = if k == 1 {
                "bitmaps".to_owned()
             } else {
-               k
+               DataElements::label_id(k)
             };
-            let iso_field = iso_fields_ref.into_iter().find(|field| field.label_id == current_label_id);
+            let iso_field = iso_fields_ref.iter().find(|field| field.label_id == current_label_id);
             println!("orig: {} => {}", iso_field.unwrap().label, v.get_string());
         }
         println!("\n\n{:?}\n\n", &original_group.pds);
@@ -124,13 +135,13 @@ fn parse_t121_deblocked_sample() {
         let message = iso8583_file.clone().messages.get(*g).unwrap().clone();
         let data_elements = message.data_elements.clone();
         for (k, v) in data_elements {
-            let current_label_id = if k == "001" {
+            let current_label_id = if k == 1 {
                "bitmaps".to_owned()
             } else {
-               k
+               DataElements::label_id(k)
             };
 
-            let iso_field = iso_fields_ref.into_iter().find(|field| field.label_id == current_label_id);
+            let iso_field = iso_fields_ref.iter().find(|field| field.label_id == current_label_id);
             println!("orig: {} => {}", iso_field.unwrap().label, v.get_string());
         }
 
@@ -147,7 +158,7 @@ fn parse_r119_binary() {
 
     let mut payload = vec![0; metadata.len() as usize];
 
-    file.read(&mut payload).expect("buffer overflow");
+    file.read_exact(&mut payload).expect("buffer overflow");
 
     let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
 
@@ -155,3 +166,1007 @@ fn parse_r119_binary() {
         println!("{:?}", message);
     }
 }
+
+#[test]
+fn validate_sample_files() {
+    for file_name in ["tests/T121_sample_2.ipm", "tests/R119_files_processor.ipm"] {
+        let payload = iso8583::file_utils::read_file(file_name).unwrap();
+
+        let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
+
+        let report = iso8583_file.validate();
+
+        assert!(report.is_valid(), "{}: {:?}", file_name, report.violations);
+    }
+}
+
+#[test]
+fn validate_message_numbers_of_sample_file() {
+    use iso8583::validation::ViolationKind;
+
+    // the fourth message of this sample repeats the message number 3
+    let payload = iso8583::file_utils::read_file("tests/T121_sample.ipm").unwrap();
+    let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
+
+    let report = iso8583_file.validate();
+    let violations: Vec<(usize, &str, &ViolationKind)> = report
+        .violations
+        .iter()
+        .map(|violation| (violation.message_index, violation.field_id.as_str(), &violation.kind))
+        .collect();
+
+    assert_eq!(
+        violations,
+        vec![
+            (3, "071", &ViolationKind::DuplicateMessageNumber),
+            (4, "071", &ViolationKind::MessageNumberGap),
+        ]
+    );
+}
+
+#[test]
+fn flag_malformed_pds_of_sample_file() {
+    use iso8583::validation::ViolationKind;
+
+    // this sample has its DE 48 scrubbed, leaving lengths that go beyond the data element
+    let payload = iso8583::file_utils::read_file("tests/R111_sample.ipm").unwrap();
+    let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
+    assert_eq!(iso8583_file.messages.len(), 5);
+
+    for message in iso8583_file.messages.iter() {
+        let additional_data = message.data_elements[48].get_string();
+        assert_eq!(message.pds.malformed().len(), 1);
+        assert_eq!(message.pds.encode(48), additional_data);
+    }
+
+    let report = iso8583_file.validate();
+    let malformed_pds = report
+        .violations
+        .iter()
+        .filter(|violation| violation.kind == ViolationKind::MalformedPds)
+        .count();
+    assert_eq!(malformed_pds, 5);
+}
+
+#[test]
+fn write_parsed_sample_files_back_record_by_record() {
+    let specs = IsoSpecs::new();
+    let sample_files = [
+        "tests/R119_files_processor.ipm",
+        "tests/T112_empty.ipm",
+        "tests/T121_sample.ipm",
+        "tests/T121_sample_2.ipm",
+    ];
+
+    for file_name in sample_files {
+        let payload = iso8583::file_utils::read_file(file_name).unwrap();
+        let (deblocked, records) = iso8583::file_utils::deblock_into_records(payload.clone()).unwrap();
+        let records = records.unwrap();
+
+        let iso8583_file: iso8583::Iso8583File = iso8583::parse_file(payload).unwrap();
+        assert_eq!(iso8583_file.messages.len(), records.len(), "{}", file_name);
+
+        for (index, (message, record)) in iso8583_file.messages.iter().zip(records.iter()).enumerate() {
+            let encoded = iso8583::writer::encode_message(&specs, message).unwrap();
+            assert_eq!(encoded, &deblocked[record.clone()], "{} message {}", file_name, index);
+        }
+
+        let written = iso8583::writer::write_file(&iso8583_file.messages, true).unwrap();
+        let (written_deblocked, _) = iso8583::file_utils::deblock_into_records(written).unwrap();
+        assert_eq!(written_deblocked, deblocked, "{}", file_name);
+    }
+}
+
+#[test]
+fn encode_iso_msgs_back_from_sample_files() {
+    use iso8583::iso_msg::{BitmapFormat, BitmapLayout};
+
+    let iso_specs = IsoSpecs::new();
+    let sample_files = [
+        "tests/R111_sample.ipm",
+        "tests/R119_files_processor.ipm",
+        "tests/T112_empty.ipm",
+        "tests/T121_sample.ipm",
+        "tests/T121_sample_2.ipm",
+    ];
+
+    for file_name in sample_files {
+        let payload = iso8583::read_and_deblock_file(file_name).unwrap();
+        let mut position = 0usize;
+
+        while payload.len() > position + 2 {
+            let iso_msg = IsoMsg::new(&iso_specs, &payload[position..]).unwrap();
+            let original = &payload[position..position + iso_msg.length()];
+
+            assert_eq!(iso_msg.to_byte_array(BitmapFormat::Binary), original, "{}", file_name);
+
+            let hex_encoded = iso_msg.to_byte_array(BitmapFormat::Hex);
+            assert_eq!(hex_encoded.len(), original.len() + 16);
+            assert_eq!(&hex_encoded[0..4], &original[0..4]);
+            assert_eq!(&hex_encoded[36..], &original[20..]);
+            let hex_layout = BitmapLayout {
+                format: BitmapFormat::Hex,
+                ..BitmapLayout::default()
+            };
+            assert_eq!(
+                hex_layout.decode(&hex_encoded[4..]).unwrap(),
+                (BitmapLayout::default().decode(&original[4..]).unwrap().0, 32)
+            );
+
+            position += iso_msg.length();
+        }
+    }
+}
+
+#[test]
+fn set_field_and_encode_iso_msg() {
+    use iso8583::iso_msg::BitmapFormat;
+
+    let iso_specs = IsoSpecs::new();
+    let payload = iso8583::read_and_deblock_file("tests/T112_empty.ipm").unwrap();
+
+    let mut iso_msg = IsoMsg::new(&iso_specs, &payload).unwrap();
+    iso_msg.set_field(2, b"5499999999999998").unwrap();
+    iso_msg.set_field(71, b"00000009").unwrap();
+    iso_msg.remove_field(48).unwrap();
+    assert!(iso_msg.set_field(24, b"69").is_err());
+    assert!(iso_msg.set_field(1000, b"69").is_err());
+    assert!(iso_msg.set_field(41, b"TERMINAL1").is_err());
+    assert!(iso_msg.remove_field(1000).is_err());
+
+    let encoded = iso_msg.to_byte_array(BitmapFormat::Binary);
+    let parsed = iso8583::parse_file(encoded).unwrap();
+    let message = &parsed.messages[0];
+
+    assert_eq!(message.primary_bitmap, [0xC0, 0, 1, 0, 0, 0, 0, 0]);
+    assert_eq!(message.data_elements["002"].get_string(), "5499999999999998");
+    assert_eq!(message.data_elements["024"].get_string(), "697");
+    assert_eq!(message.data_elements["071"].get_string(), "9");
+    assert!(!message.data_elements.contains_key("048"));
+}
+
+#[test]
+fn parse_hex_bitmap_without_secondary_bitmap() {
+    use iso8583::iso_msg::{BitmapFormat, BitmapLayout, SecondaryBitmap};
+
+    let iso_specs = IsoSpecs::new().with_bitmap_layout(BitmapLayout {
+        format: BitmapFormat::Hex,
+        secondary: SecondaryBitmap::WhenFlagged,
+        tertiary: false,
+    });
+    let payload = b"16447000010000000000165499999999999998000000000000001500697".to_vec();
+
+    let iso8583_file = iso8583::parse_file_with_specs(payload.clone(), &iso_specs).unwrap();
+    let message = &iso8583_file.messages[0];
+
+    assert_eq!(message.primary_bitmap, [0x70, 0, 1, 0, 0, 0, 0, 0]);
+    assert!(!message.data_elements.contains_key("001"));
+    assert_eq!(message.data_elements["002"].get_string(), "5499999999999998");
+    assert_eq!(message.data_elements["004"].get_string(), "1500");
+    assert_eq!(message.data_elements["024"].get_string(), "697");
+
+    let iso_msg = IsoMsg::new(&iso_specs, &payload).unwrap();
+    assert_eq!(iso_msg.to_byte_array(BitmapFormat::Hex), payload);
+    assert_eq!(iso8583::writer::encode_message(&iso_specs, message).unwrap(), payload);
+}
+
+proptest::proptest! {
+    #[test]
+    fn bitmaps_round_trip(
+        field_numbers in proptest::collection::btree_set(2usize..=192, 0..40),
+        hex in proptest::bool::ANY,
+        always_secondary in proptest::bool::ANY,
+        tertiary in proptest::bool::ANY,
+    ) {
+        use iso8583::iso_msg::{BitmapFormat, BitmapLayout, SecondaryBitmap};
+
+        let layout = BitmapLayout {
+            format: if hex { BitmapFormat::Hex } else { BitmapFormat::Binary },
+            secondary: if always_secondary { SecondaryBitmap::Always } else { SecondaryBitmap::WhenFlagged },
+            tertiary,
+        };
+        let field_numbers = field_numbers.into_iter().filter(|&number| tertiary || number <= 128);
+        let bitmap = layout.bitmap_for(field_numbers);
+        let encoded = layout.encode(&bitmap);
+
+        proptest::prop_assert_eq!(layout.decode(&encoded).unwrap(), (bitmap, encoded.len()));
+    }
+
+    #[test]
+    fn set_fields_round_trip(
+        pan in "[0-9]{1,19}",
+        amount in "[0-9]{12}",
+        terminal_id in "[A-Z0-9]{8}",
+        card_acceptor in "[A-Z0-9]{1,99}",
+    ) {
+        use iso8583::iso_msg::BitmapFormat;
+
+        let iso_specs = IsoSpecs::new();
+        let payload = iso8583::read_and_deblock_file("tests/T112_empty.ipm").unwrap();
+
+        let mut iso_msg = IsoMsg::new(&iso_specs, &payload).unwrap();
+        iso_msg.set_field(2, pan.as_bytes()).unwrap();
+        iso_msg.set_field(4, amount.as_bytes()).unwrap();
+        iso_msg.set_field(41, terminal_id.as_bytes()).unwrap();
+        iso_msg.set_field(43, card_acceptor.as_bytes()).unwrap();
+
+        let encoded = iso_msg.to_byte_array(BitmapFormat::Binary);
+        let reparsed = IsoMsg::new(&iso_specs, &encoded).unwrap();
+        proptest::prop_assert_eq!(reparsed.length(), encoded.len());
+        proptest::prop_assert_eq!(reparsed.bitmap(), iso_msg.bitmap());
+
+        let message = iso8583::parse_message(&encoded, &iso_specs).unwrap();
+        proptest::prop_assert_eq!(message.data_elements["002"].get_string(), pan);
+        proptest::prop_assert_eq!(
+            message.data_elements["004"].get_string(),
+            amount.parse::<u64>().unwrap().to_string()
+        );
+        proptest::prop_assert_eq!(message.data_elements["041"].get_string(), terminal_id);
+        proptest::prop_assert_eq!(message.data_elements["043"].get_string(), card_acceptor);
+    }
+}
+
+#[test]
+fn malformed_iso_msg_is_an_error() {
+    use iso8583::iso_msg::{BitmapFormat, BitmapLayout, SecondaryBitmap};
+
+    let iso_specs = IsoSpecs::new().with_bitmap_layout(BitmapLayout {
+        format: BitmapFormat::Hex,
+        secondary: SecondaryBitmap::WhenFlagged,
+        tertiary: false,
+    });
+
+    let invalid_bitmap = b"16447Z00010000000000165499999999999998000000000000001500697";
+    assert!(IsoMsg::new(&iso_specs, invalid_bitmap).is_err());
+
+    let invalid_prefix = b"16447000010000000000X65499999999999998000000000000001500697";
+    assert!(IsoMsg::new(&iso_specs, invalid_prefix).is_err());
+
+    let truncated = b"164470000100000000001654999999999999980000000000000015";
+    assert!(IsoMsg::new(&iso_specs, truncated).is_err());
+
+    assert!(IsoMsg::new(&iso_specs, b"16").is_err());
+}
+
+#[test]
+fn parse_tertiary_bitmap() {
+    use iso8583::iso_field::{FieldCharType, FieldSizeType, IsoField};
+    use iso8583::iso_msg::{BitmapFormat, BitmapLayout, SecondaryBitmap};
+
+    let mut iso_specs = IsoSpecs::new().with_bitmap_layout(BitmapLayout {
+        format: BitmapFormat::Binary,
+        secondary: SecondaryBitmap::WhenFlagged,
+        tertiary: true,
+    });
+    iso_specs.specs.push(IsoField::new(
+        "Reserved for Private use",
+        "129",
+        FieldCharType::Iso8583_ans,
+        99,
+        FieldSizeType::LlVar,
+    ));
+
+    let mut payload = b"1644".to_vec();
+    payload.extend_from_slice(&[0x80, 0, 1, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(&[0x82, 0, 0, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(&[0x80, 0, 0, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(b"6970000000105HELLO");
+
+    let iso8583_file = iso8583::parse_file_with_specs(payload.clone(), &iso_specs).unwrap();
+    let message = &iso8583_file.messages[0];
+
+    assert_eq!(message.data_elements["065"].get_string(), format!("{:?}", [0x80u8, 0, 0, 0, 0, 0, 0, 0]));
+    assert_eq!(message.data_elements["071"].get_string(), "1");
+    assert_eq!(message.data_elements["129"].get_string(), "HELLO");
+
+    let iso_msg = IsoMsg::new(&iso_specs, &payload).unwrap();
+    assert_eq!(iso_msg.length(), payload.len());
+    assert_eq!(iso_msg.to_byte_array(BitmapFormat::Binary), payload);
+    assert_eq!(iso8583::writer::encode_message(&iso_specs, message).unwrap(), payload);
+}
+
+#[test]
+fn parse_and_encode_bcd_binary_and_ebcdic_fields() {
+    use iso8583::iso_field::FieldEncoding;
+
+    let mut iso_specs = IsoSpecs::new();
+    iso_specs.specs[2].length_encoding = FieldEncoding::Binary;
+    iso_specs.specs[2].content_encoding = FieldEncoding::BcdRightPadded;
+    iso_specs.specs[4].content_encoding = FieldEncoding::BcdLeftPadded;
+    iso_specs.specs[43].length_encoding = FieldEncoding::BcdLeftPadded;
+    iso_specs.specs[43].content_encoding = FieldEncoding::Ebcdic;
+
+    let mut payload = b"1240".to_vec();
+    payload.extend_from_slice(&[0xD0, 0, 1, 0, 0, 0x20, 0, 0]);
+    payload.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+    payload.extend_from_slice(&[0x13, 0x54, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x8F]);
+    payload.extend_from_slice(&[0, 0, 0, 0, 0x15, 0]);
+    payload.extend_from_slice(b"200");
+    payload.extend_from_slice(&[0x04, 0xC1, 0xC2, 0x40, 0xF1]);
+
+    let iso8583_file = iso8583::parse_file_with_specs(payload.clone(), &iso_specs).unwrap();
+    let message = &iso8583_file.messages[0];
+
+    assert_eq!(message.data_elements["002"].get_string(), "5499999999999999998");
+    assert_eq!(message.data_elements["004"].get_string(), "1500");
+    assert_eq!(message.data_elements["043"].get_string(), "AB 1");
+
+    let mut iso_msg = IsoMsg::new(&iso_specs, &payload).unwrap();
+    assert_eq!(iso_msg.length(), payload.len());
+    assert_eq!(iso8583::writer::encode_message(&iso_specs, message).unwrap(), payload);
+
+    iso_msg.set_field(43, b"XY").unwrap();
+    let encoded = iso_msg.to_byte_array(iso8583::iso_msg::BitmapFormat::Binary);
+    assert_eq!(&encoded[encoded.len() - 3..], &[0x02, 0xE7, 0xE8]);
+}
+
+#[test]
+fn build_and_parse_online_messages() {
+    use iso8583::builder::MessageBuilder;
+    use strum::EnumProperty;
+
+    let iso_specs = IsoSpecs::iso8583_1987();
+
+    let authorization = MessageBuilder::new(&iso_specs)
+        .mti("0100")
+        .de(2, "5499999999999998")
+        .de(3, "000000")
+        .de(4, "1500")
+        .de(7, "1018120000")
+        .de(11, "123456")
+        .de(22, "051")
+        .de(41, "TERM0001")
+        .de(49, "986")
+        .build()
+        .unwrap();
+    assert!(!authorization.data_elements.contains_key("001"));
+
+    let payload = iso8583::writer::encode_message(&iso_specs, &authorization).unwrap();
+    assert_eq!(&payload[4..12], &[0x72, 0x20, 0x04, 0, 0x00, 0x80, 0x80, 0]);
+    assert_eq!(IsoMsg::new(&iso_specs, &payload).unwrap().length(), payload.len());
+
+    let parsed = iso8583::parse_message(&payload, &iso_specs).unwrap();
+    assert_eq!(parsed.category.get_str("name"), Some("authorization_requests"));
+    assert!(parsed.category.is_online());
+    assert_eq!(parsed.data_elements["041"].get_string(), "TERM0001");
+    assert!(parsed.pds.is_empty());
+
+    let reversal = MessageBuilder::new(&iso_specs)
+        .mti("0400")
+        .de(3, "000000")
+        .de(4, "1500")
+        .de(11, "123457")
+        .de(90, "010012345610181200000000000012300000000000")
+        .build()
+        .unwrap();
+
+    let payload = iso8583::writer::encode_message(&iso_specs, &reversal).unwrap();
+    let parsed = iso8583::parse_message(&payload, &iso_specs).unwrap();
+    assert_eq!(parsed.category.get_str("kind"), Some("reversal_messages"));
+    assert_eq!(parsed.data_elements["090"].get_string(), "010012345610181200000000000012300000000000");
+
+    let online_file = iso8583::parse_file_with_specs(
+        iso8583::file_utils::add_rdw_to(&[iso8583::writer::encode_message(&iso_specs, &authorization).unwrap(), payload]),
+        &iso_specs,
+    )
+    .unwrap();
+    assert_eq!(online_file.categories_indexes["reversal_requests"], vec![1]);
+    assert!(online_file.validate_with_specs(&iso_specs).is_valid());
+}
+
+#[test]
+fn exchange_framed_messages_with_loopback_server() {
+    use iso8583::builder::MessageBuilder;
+    use iso8583::framing::{Framing, LengthHeader, MessageStream, Tpdu};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    let framing = Framing::new(LengthHeader::Binary2).with_tpdu();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    // stand-in host, answering each network management request until the client disconnects
+    let server = thread::spawn(move || {
+        let iso_specs = IsoSpecs::iso8583_1987();
+        let (socket, _) = listener.accept().unwrap();
+        let mut stream = MessageStream::new(socket, framing, &iso_specs);
+
+        let mut answered = 0;
+        while let Some(request) = stream.read_message().unwrap() {
+            let response = MessageBuilder::new(&iso_specs)
+                .mti("0810")
+                .de(7, &request.data_elements["007"].get_string())
+                .de(11, &request.data_elements["011"].get_string())
+                .de(39, "00")
+                .de(70, &request.data_elements["070"].get_string())
+                .build()
+                .unwrap();
+            stream.write_message(&response).unwrap();
+            answered += 1;
+        }
+        answered
+    });
+
+    let iso_specs = IsoSpecs::iso8583_1987();
+    let mut stream = MessageStream::new(TcpStream::connect(address).unwrap(), framing, &iso_specs);
+    let tpdu = Tpdu {
+        id: 0x60,
+        destination: [0x00, 0x05],
+        source: [0x00, 0x00],
+    };
+
+    for stan in ["1", "2"] {
+        let echo = MessageBuilder::new(&iso_specs)
+            .mti("0800")
+            .de(7, "1018120000")
+            .de(11, stan)
+            .de(70, "301")
+            .build()
+            .unwrap();
+        stream.write_message_with_tpdu(&echo, Some(tpdu)).unwrap();
+
+        let response = stream.next().unwrap().unwrap();
+        assert_eq!(response.mti, "0810");
+        assert_eq!(response.data_elements["011"].get_string(), stan);
+        assert_eq!(response.data_elements["039"].get_string(), "00");
+        assert_eq!(stream.last_tpdu, Some(tpdu.response()));
+    }
+
+    stream.get_ref().shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(server.join().unwrap(), 2);
+}
+
+#[test]
+fn parse_files_with_elo_profile() {
+    use iso8583::builder::MessageBuilder;
+    use iso8583::iso_specs::{Category, Profile};
+    use strum::EnumProperty;
+
+    let payload = iso8583::read_and_deblock_file("tests/T121_sample.ipm").unwrap();
+    let mastercard_file = iso8583::parse_file(payload.clone()).unwrap();
+    let elo_file = iso8583::parse_file_with_specs(payload, &IsoSpecs::elo()).unwrap();
+    assert_eq!(elo_file.categories_indexes, mastercard_file.categories_indexes);
+
+    let header = &elo_file.messages[elo_file.categories_indexes["headers"][0]];
+    assert!(header.named_pds(Profile::Elo).contains_key("File ID"));
+
+    for (iso_specs, expected_name) in [(IsoSpecs::new(), "currency_updates"), (IsoSpecs::elo(), "unknown")] {
+        let currency_update = MessageBuilder::new(&iso_specs)
+            .mti("1644")
+            .de(24, "640")
+            .de(71, "2")
+            .pds("0164", "986")
+            .build()
+            .unwrap();
+        assert_eq!(currency_update.category.get_str("name"), Some(expected_name));
+    }
+
+    assert_eq!(Profile::from_name("elo"), Some(Profile::Elo));
+    assert!(!Category::FeeCollectionClearing.applies_to(Profile::Elo));
+    assert!(!Category::FirstPresentment.applies_to(Profile::Elo));
+    assert!(Category::EloFirstPresentment.applies_to(Profile::Elo));
+    assert!(Category::FirstChargeback.applies_to(Profile::Elo));
+}
+
+#[test]
+fn parse_elo_sample_file() {
+    use iso8583::builder::MessageBuilder;
+    use iso8583::iso_specs::{Category, Profile};
+    use iso8583::validation::ViolationKind;
+    use strum::EnumProperty;
+
+    let iso_specs = IsoSpecs::elo();
+    let financial = |mti: &str, function_code: &str, message_number: &str| {
+        MessageBuilder::new(&iso_specs)
+            .mti(mti)
+            .de(2, "6363680000000015")
+            .de(3, "000000")
+            .de(4, "10000")
+            .de(12, "210514120000")
+            .de(22, "510101511344")
+            .de(24, function_code)
+            .de(26, "5411")
+            .de(31, "75123453123000000000015")
+            .de(33, "002001")
+            .de(42, "000000000012345")
+            .de(43, "SUPERMERCADO\\SAO PAULO\\")
+            .de(49, "986")
+            .de(71, message_number)
+            .pds("0023", "POI")
+            .pds("0195", "12345678000199")
+    };
+
+    let messages = vec![
+        MessageBuilder::new(&iso_specs)
+            .mti("1644")
+            .de(24, "697")
+            .de(71, "1")
+            .pds("0105", "0012105140000002337906601")
+            .pds("0122", "P")
+            .build()
+            .unwrap(),
+        financial("1240", "200", "2").build().unwrap(),
+        financial("1240", "210", "3")
+            .pds("0181", "200400000000000002500000000002500")
+            .pds("0182", "01")
+            .build()
+            .unwrap(),
+        financial("1442", "451", "4")
+            .de(25, "4808")
+            .de(30, "000000010000000000010000")
+            .pds("0181", "200400000000000002500000000002500")
+            .build()
+            .unwrap(),
+        MessageBuilder::new(&iso_specs)
+            .mti("1644")
+            .de(24, "695")
+            .de(71, "5")
+            .pds("0105", "0012105140000002337906601")
+            .pds("0306", "00000005")
+            .build()
+            .unwrap(),
+    ];
+    let payload = iso8583::writer::write_file_with_specs(&messages, true, &iso_specs).unwrap();
+
+    let elo_file = iso8583::parse_file_with_specs(payload.clone(), &iso_specs).unwrap();
+    let categories: Vec<Option<&str>> = elo_file
+        .messages
+        .iter()
+        .map(|message| message.category.get_str("name"))
+        .collect();
+    assert_eq!(
+        categories,
+        vec![
+            Some("headers"),
+            Some("first_presentments"),
+            Some("installment_presentments"),
+            Some("installment_chargebacks"),
+            Some("trailers"),
+        ]
+    );
+    assert!(matches!(elo_file.messages[1].category, Category::EloFirstPresentment));
+
+    let named_pds = elo_file.messages[2].named_pds(Profile::Elo);
+    assert_eq!(named_pds["Installment Payment Data (Parcelado)"], "200400000000000002500000000002500");
+    assert_eq!(named_pds["Installment Number (Parcela)"], "01");
+    assert_eq!(named_pds["Merchant Tax ID (CNPJ/CPF)"], "12345678000199");
+    assert_eq!(elo_file.messages[2].installment().unwrap().unwrap().number_of_installments, 4);
+
+    let report = elo_file.validate_with_specs(&iso_specs);
+    assert!(report.is_valid(), "{:?}", report.violations);
+
+    // the same file read as Mastercard misses DE 63 on first presentments and the Elo function codes
+    let mastercard_file = iso8583::parse_file(payload).unwrap();
+    assert_eq!(mastercard_file.messages[2].category.get_str("name"), Some("unknown"));
+    assert_eq!(mastercard_file.messages[3].category.get_str("name"), Some("unknown"));
+    let violations = mastercard_file.validate().violations;
+    assert!(violations
+        .iter()
+        .any(|violation| violation.message_index == 1 && violation.kind == ViolationKind::MissingDataElement));
+}
+
+#[test]
+fn export_installment_data() {
+    use iso8583::builder::MessageBuilder;
+
+    let iso_specs = IsoSpecs::elo();
+    let message = MessageBuilder::new(&iso_specs)
+        .mti("1240")
+        .de(4, "100")
+        .de(24, "200")
+        .pds("0181", "210300000000000000034000000000033")
+        .build()
+        .unwrap();
+
+    let installment = message.installment().unwrap().unwrap();
+    assert_eq!(installment.number_of_installments, 3);
+    assert_eq!(installment.installment_amount, 33);
+
+    let exported = serde_json::to_value(message.decoded()).unwrap();
+    assert_eq!(exported["installment"]["first_installment_amount"], 34);
+    assert_eq!(exported["installment"]["plan_type"], "21");
+    assert_eq!(exported["mti"], "1240");
+    assert_eq!(exported["pds"]["0181"], "210300000000000000034000000000033");
+    assert!(serde_json::to_value(&message).unwrap().get("installment").is_none());
+}
+
+#[test]
+fn detect_sample_files_type() {
+    use iso8583::iso_specs::{BulkType, FileType};
+    use strum::EnumProperty;
+
+    for (file_name, expected_file_type, expected_bulk_type) in [
+        ("tests/T112_empty.ipm", FileType::OutboundClearing, BulkType::T112),
+        ("tests/R119_files_processor.ipm", FileType::OutboundClearing, BulkType::R119),
+        ("tests/R111_sample.ipm", FileType::InboundClearing, BulkType::R111),
+        ("tests/T121_sample.ipm", FileType::Notification, BulkType::T121),
+        ("tests/T121_sample_2.ipm", FileType::Notification, BulkType::T121),
+    ] {
+        let payload = iso8583::read_and_deblock_file(file_name).unwrap();
+        let iso8583_file = iso8583::parse_file(payload).unwrap();
+
+        assert_eq!(iso8583_file.file_type(), expected_file_type, "{}", file_name);
+        assert_eq!(iso8583_file.bulk_type(), expected_bulk_type, "{}", file_name);
+        assert_eq!(BulkType::from_file_name(file_name), Some(expected_bulk_type));
+    }
+
+    // the header of R111_sample has no readable PDS, so its file type comes from its categories
+    let payload = iso8583::read_and_deblock_file("tests/R111_sample.ipm").unwrap();
+    assert!(iso8583::parse_file(payload).unwrap().file_id().unwrap().is_none());
+
+    assert_eq!(BulkType::from_file_name("TT464T0.2021-05-14-10-00-00.001"), Some(BulkType::T464));
+    assert_eq!(BulkType::from_file_name("tt140t0.001"), Some(BulkType::T140));
+    assert_eq!(BulkType::from_file_name("clearing.ipm"), None);
+    assert_eq!(
+        FileType::OutboundClearing.bulk_types(),
+        vec![BulkType::T112, BulkType::R119, BulkType::T140, BulkType::T461, BulkType::T464]
+    );
+    assert_eq!(FileType::Notification.bulk_types(), vec![BulkType::T121, BulkType::T113]);
+    assert_eq!(BulkType::T113.file_type(), FileType::Notification);
+    assert_eq!(FileType::from_categories(["headers", "trailers"]), FileType::Unknown);
+    assert_eq!(FileType::from_categories(["headers", "file_rejects"]), FileType::Notification);
+    assert_eq!(
+        FileType::from_categories(["first_presentments", "settlements"]),
+        FileType::OutboundClearing
+    );
+
+    let payload = iso8583::read_and_deblock_file("tests/T121_sample.ipm").unwrap();
+    let file_id = iso8583::parse_file(payload).unwrap().file_id().unwrap().unwrap();
+    assert_eq!(file_id.file_reference_date, "210527");
+    assert_eq!(file_id.processor_id, "00000023379");
+
+    assert_eq!(FileType::Notification.get_str("direction"), Some("outbound"));
+    assert_eq!(FileType::from_file_type("002"), FileType::InboundClearing);
+}
+
+#[test]
+fn borrow_messages_from_deblocked_sample() {
+    use iso8583::message_ref::MessageRefs;
+    use strum::EnumProperty;
+
+    let iso_specs = IsoSpecs::new();
+    let payload = iso8583::read_and_deblock_file("tests/T121_sample.ipm").unwrap();
+    let iso8583_file = iso8583::parse_file(payload.clone()).unwrap();
+
+    let message_refs: Vec<_> = MessageRefs::new(&payload, &iso_specs).collect::<Result<_, _>>().unwrap();
+    assert_eq!(message_refs.len(), iso8583_file.messages.len());
+
+    for (message_ref, message) in message_refs.iter().zip(iso8583_file.messages.iter()) {
+        assert_eq!(message_ref.mti().unwrap(), message.mti);
+        assert_eq!(message_ref.category().unwrap().get_str("name"), message.category.get_str("name"));
+
+        let owned = message_ref.to_message().unwrap();
+        assert_eq!(owned.data_elements.len(), message.data_elements.len());
+        assert_eq!(owned.pds, message.pds);
+        assert_eq!(message_ref.pds("0105").unwrap().as_deref(), message.pds.get("0105").map(|v| v.as_str()));
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn parse_sample_files_in_parallel() {
+    let assert_same_messages = |parallel: &iso8583::Iso8583File, sequential: &iso8583::Iso8583File, name: &str| {
+        assert_eq!(parallel.messages.len(), sequential.messages.len(), "{}", name);
+        assert_eq!(parallel.categories_indexes, sequential.categories_indexes, "{}", name);
+        for (parallel_message, message) in parallel.messages.iter().zip(sequential.messages.iter()) {
+            assert_eq!(parallel_message.mti, message.mti, "{}", name);
+            assert_eq!(parallel_message.primary_bitmap, message.primary_bitmap, "{}", name);
+            assert!(parallel_message.data_elements == message.data_elements, "{}", name);
+            assert_eq!(parallel_message.pds, message.pds, "{}", name);
+        }
+    };
+
+    for file_name in [
+        "tests/R111_sample.ipm",
+        "tests/R119_files_processor.ipm",
+        "tests/T112_empty.ipm",
+        "tests/T121_sample.ipm",
+        "tests/T121_sample_2.ipm",
+    ] {
+        let payload = iso8583::file_utils::read_file(file_name).unwrap();
+
+        let sequential = iso8583::parse_file(payload.clone()).unwrap();
+        let parallel = iso8583::parse_file_parallel(payload).unwrap();
+        assert_same_messages(&parallel, &sequential, file_name);
+    }
+
+    // a record holding two messages gives both of them, as on sequential parsing
+    let payload = iso8583::file_utils::read_file("tests/T121_sample_2.ipm").unwrap();
+    let (deblocked, records) = iso8583::file_utils::deblock_into_records(payload).unwrap();
+    let records = records.unwrap();
+    let mut merged_records: Vec<Vec<u8>> = records.iter().map(|record| deblocked[record.clone()].to_vec()).collect();
+    let second = merged_records.remove(1);
+    merged_records[0].extend_from_slice(&second);
+    let payload = iso8583::file_utils::add_rdw_to(&merged_records);
+
+    let sequential = iso8583::parse_file(payload.clone()).unwrap();
+    let parallel = iso8583::parse_file_parallel(payload).unwrap();
+    assert_eq!(parallel.messages.len(), records.len());
+    assert_same_messages(&parallel, &sequential, "merged records");
+}
+
+#[test]
+fn decode_sample_files_lazily() {
+    use iso8583::lazy_file::LazyFile;
+
+    for file_name in ["tests/T121_sample.ipm", "tests/T121_sample_2.ipm", "tests/R119_files_processor.ipm"] {
+        let payload = iso8583::file_utils::read_file(file_name).unwrap();
+        let iso8583_file = iso8583::parse_file(payload.clone()).unwrap();
+        let lazy_file = LazyFile::from_bytes(payload, IsoSpecs::new()).unwrap();
+
+        assert_eq!(lazy_file.len(), iso8583_file.messages.len(), "{}", file_name);
+
+        let last = lazy_file.len() - 1;
+        assert_eq!(lazy_file.message(last).unwrap().pds, iso8583_file.messages[last].pds);
+
+        for (lazy_message, message) in lazy_file.messages().zip(iso8583_file.messages.iter()) {
+            assert_eq!(lazy_message.unwrap().mti, message.mti);
+        }
+    }
+
+    let deblocked = iso8583::read_and_deblock_file("tests/T121_sample.ipm").unwrap();
+    let messages_count = iso8583::parse_file(deblocked.clone()).unwrap().messages.len();
+    assert_eq!(LazyFile::from_bytes(deblocked, IsoSpecs::new()).unwrap().len(), messages_count);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn map_sample_file_into_memory() {
+    use iso8583::lazy_file::LazyFile;
+
+    let lazy_file = LazyFile::open("tests/R119_files_processor.ipm", IsoSpecs::new()).unwrap();
+    let iso8583_file = iso8583::parse_file(iso8583::file_utils::read_file("tests/R119_files_processor.ipm").unwrap()).unwrap();
+
+    assert_eq!(lazy_file.len(), iso8583_file.messages.len());
+    assert_eq!(lazy_file.message(40).unwrap().data_elements.len(), iso8583_file.messages[40].data_elements.len());
+
+    assert!(LazyFile::open("tests/missing.ipm", IsoSpecs::new()).is_err());
+}
+
+#[test]
+fn access_data_elements_by_number() {
+    let payload = iso8583::read_and_deblock_file("tests/T121_sample.ipm").unwrap();
+    let iso8583_file = iso8583::parse_file(payload).unwrap();
+    let message = &iso8583_file.messages[0];
+
+    assert_eq!(message.de(24).unwrap().get_string(), message.data_elements["024"].get_string());
+    assert!(message.de(2).is_none());
+
+    let numbers: Vec<usize> = message.data_elements.numbers().collect();
+    let mut sorted_numbers = numbers.clone();
+    sorted_numbers.sort();
+    assert_eq!(numbers, sorted_numbers);
+
+    // serialized in DE order
+    let exported = serde_json::to_string(&message.data_elements).unwrap();
+    let positions: Vec<usize> = message
+        .data_elements
+        .label_ids()
+        .map(|label_id| exported.find(&format!("\"{}\"", label_id)).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn find_messages_by_indexed_values() {
+    use iso8583::query::Selector;
+
+    let payload = iso8583::file_utils::read_file("tests/R119_files_processor.ipm").unwrap();
+    let mut iso8583_file = iso8583::parse_file(payload).unwrap();
+
+    let arn_message = iso8583_file.messages.iter().position(|message| message.de(31).is_some()).unwrap();
+    let arn = iso8583_file.messages[arn_message].de(31).unwrap().get_string();
+
+    assert!(iso8583_file.find_by(&Selector::De(31), &arn).is_err());
+
+    iso8583_file.build_index(Selector::De(31));
+    iso8583_file.build_index(Selector::Pds("0158".to_string()));
+
+    let found = iso8583_file.find_by(&Selector::De(31), &arn).unwrap();
+    assert!(found.contains(&arn_message));
+    assert_eq!(found, iso8583_file.find_indexes(&Query::de(31).eq(&arn)).as_slice());
+    assert!(iso8583_file.find_by(&Selector::De(31), "00000000000000000000000").unwrap().is_empty());
+
+    let business_activity = Selector::Pds("0158".to_string());
+    for message in iso8583_file.messages.iter() {
+        if let Some(value) = message.pds.get("0158") {
+            let indexes = iso8583_file.find_by(&business_activity, value).unwrap();
+            assert_eq!(indexes, iso8583_file.find_indexes(&Query::pds("0158").eq(value)).as_slice());
+        }
+    }
+}
+
+#[test]
+fn diff_corrected_file() {
+    use iso8583::diff::{diff_files, Alignment, MessageDiff};
+    use iso8583::iso_field::IPMValue;
+    use iso8583::query::Selector;
+
+    let payload = iso8583::file_utils::read_file("tests/R119_files_processor.ipm").unwrap();
+    let original = iso8583::parse_file(payload).unwrap();
+    assert!(diff_files(&original, &original, &Alignment::Position).is_empty());
+
+    let changed_index = original.messages.iter().position(|message| message.de(31).is_some()).unwrap();
+    let mut corrected = original.clone();
+    corrected.messages[changed_index].data_elements.set_de(4, IPMValue::u64(1));
+    let removed = corrected.messages.remove(changed_index + 1);
+
+    let alignment = Alignment::Key(vec![Selector::De(31), Selector::De(71)]);
+    let file_diff = diff_files(&original, &corrected, &alignment);
+
+    assert_eq!(file_diff.messages.len(), 2, "{}", file_diff);
+    match &file_diff.messages[0] {
+        MessageDiff::Changed { left_index, fields, .. } => {
+            assert_eq!(*left_index, changed_index);
+            assert_eq!(fields[0].field_id, "004");
+            assert_eq!(fields[0].right.as_deref(), Some("1"));
+        }
+        message_diff => panic!("unexpected {:?}", message_diff),
+    }
+    assert!(matches!(file_diff.messages[1], MessageDiff::Removed { left_index, .. } if left_index == changed_index + 1));
+    assert!(file_diff.to_string().starts_with(&format!("~ message {} -> {}", changed_index, changed_index)));
+
+    corrected.messages.push(removed);
+    let file_diff = diff_files(&original, &corrected, &alignment);
+    assert_eq!(file_diff.messages.len(), 1);
+
+    let exported = serde_json::to_value(&file_diff).unwrap();
+    assert_eq!(exported["messages"][0]["change"], "changed");
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn diff_files_from_the_command_line() {
+    use std::process::Command;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipm-diff"))
+        .args(["--json", "--key", "031,071", "tests/T121_sample.ipm", "tests/T121_sample_2.ipm"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let file_diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(!file_diff["messages"].as_array().unwrap().is_empty());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipm-diff"))
+        .args(["tests/T121_sample.ipm", "tests/T121_sample.ipm"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn split_and_merge_sample_file() {
+    use iso8583::diff::{diff_files, Alignment};
+    use iso8583::query::Selector;
+    use strum::EnumProperty;
+
+    let payload = iso8583::file_utils::read_file("tests/R119_files_processor.ipm").unwrap();
+    let original = iso8583::parse_file(payload).unwrap();
+    let file_id = original.file_id().unwrap().unwrap();
+
+    let specs = IsoSpecs::new();
+    let splits = original.split_by_count(40, &specs).unwrap();
+    assert_eq!(splits.len(), 3);
+    let mut written_splits = vec![];
+    for split in splits.iter() {
+        let trailer = split.messages.last().unwrap();
+        assert_eq!(trailer.pds["0306"], format!("{:08}", split.messages.len()));
+        assert_eq!(trailer.pds["0105"], split.file_id().unwrap().unwrap().value);
+        assert!(split.validate().is_valid(), "{:?}", split.validate());
+
+        let written = split.write(true, &specs).unwrap();
+        assert_eq!(written.len() % 1014, 0);
+        written_splits.push(iso8583::parse_file(written).unwrap());
+    }
+
+    // merging the written splits back gives the original file, e.g DE 93 keeping its leading zeros
+    let merged = iso8583::Iso8583File::merge(&written_splits, &file_id, &specs).unwrap();
+    let file_diff = diff_files(&original, &merged, &Alignment::Position);
+    assert!(file_diff.is_empty(), "{}", file_diff);
+
+    let by_category = original.split_by(&Selector::Category, &specs).unwrap();
+    let body_count: usize = by_category.iter().map(|(_, file)| file.body().count()).sum();
+    assert_eq!(body_count, original.body().count());
+    for (category_name, file) in by_category.iter() {
+        assert!(file.body().all(|message| message.category.get_str("name") == Some(category_name.as_str())));
+    }
+}
+
+#[test]
+fn lossless_round_trip_of_sample_files() {
+    use iso8583::iso_field::IPMValue;
+
+    let mut file_names: Vec<_> = std::fs::read_dir("tests")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "ipm"))
+        .collect();
+    file_names.sort();
+    assert_eq!(file_names.len(), 5);
+
+    for file_name in file_names {
+        let payload = std::fs::read(&file_name).unwrap();
+        let mut iso8583_file = iso8583::parse_file_lossless(payload.clone()).unwrap();
+        assert_eq!(iso8583_file.write_lossless().unwrap(), payload, "{:?}", file_name);
+
+        // changing a single field leaves the other records untouched
+        let records = |iso8583_file: &iso8583::Iso8583File| {
+            iso8583_file
+                .messages
+                .iter()
+                .map(|message| message.raw.as_ref().unwrap().bytes.clone())
+                .collect::<Vec<Vec<u8>>>()
+        };
+        let original_records = records(&iso8583_file);
+        let last = original_records.len() - 1;
+        let edited = iso8583_file.messages.iter().rposition(|message| !message.pds.is_empty()).unwrap_or(last);
+        let unchanged = |changed_records: &[Vec<u8>]| {
+            changed_records
+                .iter()
+                .zip(original_records.iter())
+                .enumerate()
+                .all(|(index, (changed, original))| (index == edited) != (changed == original))
+        };
+
+        // editing a PDS, e.g the file ID on the trailer, before resending the file. R111 has no
+        // readable PDS, its DE 48 being malformed, so one is added in front of the malformed content
+        let message = &mut iso8583_file.messages[edited];
+        message.data_elements.set_de(71, IPMValue::u64(99));
+        let (pds_id, edited_value) = match message.pds.iter().next() {
+            Some((pds_id, value)) => (pds_id.clone(), "9".repeat(value.len())),
+            None => ("0023".to_string(), "POI".to_string()),
+        };
+        let added = !message.pds.contains_key(&pds_id);
+        message.set_pds(&pds_id, &edited_value).unwrap();
+
+        let changed_file = iso8583::parse_file_lossless(iso8583_file.write_lossless().unwrap()).unwrap();
+        let changed_records = records(&changed_file);
+        assert!(unchanged(&changed_records), "{:?}", file_name);
+        assert_eq!(changed_records[edited].len() == original_records[edited].len(), !added);
+        assert_eq!(changed_file.messages[edited].data_elements[71].get_string(), "99");
+        assert_eq!(changed_file.messages[edited].pds[&pds_id], edited_value, "{:?}", file_name);
+        assert_eq!(changed_file.messages[edited].pds.malformed(), iso8583_file.messages[edited].pds.malformed());
+
+        // removing it keeps the data element in sync with the remaining PDS
+        assert_eq!(iso8583_file.messages[edited].remove_pds(&pds_id), Some(edited_value));
+        let changed_file = iso8583::parse_file_lossless(iso8583_file.write_lossless().unwrap()).unwrap();
+        assert!(unchanged(&records(&changed_file)), "{:?}", file_name);
+        assert!(!changed_file.messages[edited].pds.contains_key(&pds_id), "{:?}", file_name);
+        assert_eq!(changed_file.messages[edited].pds, iso8583_file.messages[edited].pds);
+    }
+}
+
+#[test]
+fn lossless_parsing_of_a_record_holding_two_messages() {
+    let payload = iso8583::file_utils::read_file("tests/T121_sample_2.ipm").unwrap();
+    let (deblocked, records) = iso8583::file_utils::deblock_into_records(payload).unwrap();
+    let mut merged_records: Vec<Vec<u8>> = records
+        .unwrap()
+        .iter()
+        .map(|record| deblocked[record.clone()].to_vec())
+        .collect();
+    let second = merged_records.remove(1);
+    merged_records[0].extend_from_slice(&second);
+    let iso8583_file = iso8583::parse_file(iso8583::file_utils::add_rdw_to(&merged_records)).unwrap();
+
+    // with a line feed after the second message of the record
+    merged_records[0].push(b'\n');
+    let payload = iso8583::file_utils::add_rdw_to(&merged_records);
+    let lossless_file = iso8583::parse_file_lossless(payload.clone()).unwrap();
+    assert_eq!(lossless_file.messages.len(), iso8583_file.messages.len());
+    for (lossless, message) in lossless_file.messages.iter().zip(&iso8583_file.messages) {
+        assert_eq!(lossless.mti, message.mti);
+        assert_eq!(lossless.data_elements, message.data_elements);
+    }
+
+    // only the bytes after the second message are kept along with it
+    let first = &lossless_file.messages[0].raw.as_ref().unwrap().bytes;
+    let second = &lossless_file.messages[1].raw.as_ref().unwrap().bytes;
+    assert_eq!([first.as_slice(), second.as_slice()].concat(), merged_records[0]);
+    assert_eq!(second.last(), Some(&b'\n'));
+    assert!(!first.ends_with(b"\n"));
+
+    assert_eq!(lossless_file.write_lossless().unwrap(), payload);
+}